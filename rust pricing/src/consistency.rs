@@ -0,0 +1,149 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::models::{ConsistencyReport, CostBreakdown};
+use crate::pricing::PricingProblem;
+use crate::travel_time::minutes;
+use crate::trip_cost::TripCost;
+
+impl PricingProblem {
+    /// Explains the gap between the edge cost the labeling found for
+    /// `labeling_path` and the evaluator's `trip` for the returned `path`.
+    pub(crate) fn consistency_report(
+        &self,
+        labeling_path: &[String],
+        path: &[String],
+        departure: DateTime<Utc>,
+        breakdown: &CostBreakdown,
+        trip: &TripCost,
+        tolerance: f64,
+    ) -> ConsistencyReport {
+        let edge_cost = self.calculate_path_cost(labeling_path);
+        let evaluator_cost = trip.total_cost;
+        let difference = evaluator_cost - edge_cost;
+        let evaluator_distance_cost = self.cost_per_km() * trip.total_distance;
+        let other_charges = evaluator_cost - evaluator_distance_cost;
+
+        let internal_penalties = breakdown.waiting_penalty + breakdown.late_arrival_penalty + breakdown.late_service_penalty;
+        let unrounded_penalties = self.unrounded_penalties(path, departure);
+        let evaluator_penalties = trip.total_waiting_penalty + trip.total_time_window_penalty;
+        let (penalties, rounding) = if (other_charges - evaluator_penalties).abs() < other_charges.abs() {
+            (unrounded_penalties, internal_penalties - unrounded_penalties)
+        } else {
+            (0.0, 0.0)
+        };
+        let reordering = breakdown.distance_cost - edge_cost;
+        let distance = evaluator_distance_cost - breakdown.distance_cost;
+
+        ConsistencyReport {
+            edge_cost,
+            evaluator_cost,
+            difference,
+            reordering,
+            distance,
+            penalties,
+            rounding,
+            residual: difference - reordering - distance - penalties - rounding,
+            rounding_minutes: self.rounding_minutes(path, departure),
+            internal_penalties,
+            unrounded_penalties,
+            evaluator_penalties,
+            tolerance,
+            within_tolerance: difference.abs() <= tolerance,
+        }
+    }
+
+    /// Travel minutes the route's schedule loses to truncation, each leg
+    /// taken at the time the schedule leaves along it.
+    fn rounding_minutes(&self, path: &[String], departure: DateTime<Utc>) -> f64 {
+        let mut ready = departure;
+        let mut total = 0.0;
+
+        for pair in path.windows(2) {
+            let Some(edge) = self.edge(&pair[0], &pair[1]) else { continue };
+            let arrival = self.arrival(edge, ready);
+            let exact = self.exact_travel_secs(&pair[0], &pair[1], edge, ready);
+            total += (exact - (arrival - ready).num_seconds() as f64) / 60.0;
+            ready = match self.customer(&pair[1]) {
+                Some(cust) => arrival.max(cust.window_start) + self.service_duration(),
+                None => arrival,
+            };
+        }
+
+        total
+    }
+
+    /// Penalties of the route's schedule when travel times are not truncated
+    /// to the time resolution, with the window rules of `evaluate_route`.
+    fn unrounded_penalties(&self, path: &[String], departure: DateTime<Utc>) -> f64 {
+        let penalties = self.penalties();
+        let mut ready = departure;
+        let mut total = 0.0;
+
+        for pair in path.windows(2) {
+            let Some(edge) = self.edge(&pair[0], &pair[1]) else { continue };
+            let exact = self.exact_travel_secs(&pair[0], &pair[1], edge, ready);
+            let arrival = ready + TimeDelta::milliseconds((exact * 1000.0) as i64);
+            let Some(cust) = self.customer(&pair[1]) else {
+                ready = arrival;
+                continue;
+            };
+
+            let service_start = arrival.max(cust.window_start);
+            let service_end = service_start + self.service_duration();
+            total += penalties.waiting_per_minute * minutes(service_start - arrival)
+                + penalties.late_arrival_per_minute * minutes(arrival - cust.window_end)
+                + penalties.late_service_per_minute * minutes(service_end - cust.window_end);
+            ready = service_end;
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::ConsistencyReport;
+    use crate::{build_pricing, test_input};
+
+    fn nodes(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn assert_sums_up(report: &ConsistencyReport) {
+        let causes = report.reordering + report.distance + report.penalties + report.rounding + report.residual;
+        assert!((causes - report.difference).abs() < 1e-9, "{:?}", report);
+        assert!((report.evaluator_cost - report.edge_cost - report.difference).abs() < 1e-9);
+    }
+
+    #[test]
+    fn causes_add_up_to_the_difference() {
+        let pricing = build_pricing(test_input()).unwrap();
+        let labeling_path = nodes(&["W_1", "C_3", "C_1", "C_2", "C_4", "W_1"]);
+        let path = nodes(&["W_1", "C_1", "C_2", "C_3", "C_4", "W_1"]);
+        let departure = pricing.departure_from("W_1");
+        let breakdown = pricing.evaluate_route(&path, departure).breakdown;
+        let trip = pricing.evaluator().evaluate(&path, departure).unwrap();
+
+        let report = pricing.consistency_report(&labeling_path, &path, departure, &breakdown, &trip, 1.0);
+
+        assert_sums_up(&report);
+        assert!((report.reordering - (pricing.calculate_path_cost(&path) - pricing.calculate_path_cost(&labeling_path))).abs() < 1e-9);
+        assert!((report.distance - (10.0 * trip.total_distance - breakdown.distance_cost)).abs() < 1e-9);
+        // The in-process model charges distance only
+        assert_eq!((report.penalties, report.rounding), (0.0, 0.0));
+        assert!(report.residual.abs() < 1e-9);
+        assert!(report.internal_penalties > 0.0);
+
+        // An evaluator that charges its penalties and a bit more
+        let mut charged = trip.clone();
+        charged.total_waiting_penalty = report.internal_penalties;
+        charged.total_time_window_penalty = 0.0;
+        charged.total_cost += report.internal_penalties + 5.0;
+
+        let report = pricing.consistency_report(&labeling_path, &path, departure, &breakdown, &charged, 1.0);
+
+        assert_sums_up(&report);
+        assert_eq!(report.penalties, report.unrounded_penalties);
+        assert!((report.rounding - (report.internal_penalties - report.unrounded_penalties)).abs() < 1e-9);
+        assert!((report.residual - 5.0).abs() < 1e-9);
+    }
+}
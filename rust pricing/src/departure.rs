@@ -0,0 +1,153 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::models::DepartureMode;
+use crate::pricing::PricingProblem;
+
+impl PricingProblem {
+    /// Departure of `path` from its warehouse under `departure_mode`.
+    ///
+    /// Leaving later by some delay first eats into the waiting before each
+    /// stop and only then pushes service later, so a route stays feasible
+    /// as long as the delay is within its forward slack: the least, over its
+    /// customers, of the waiting up to and including the customer plus the
+    /// time between its service end and window end. A route that is late
+    /// already at the earliest departure leaves then.
+    pub(crate) fn choose_departure(&self, path: &[String]) -> DateTime<Utc> {
+        let window = self.departure_window(path.first().map(String::as_str).unwrap_or_default());
+        if self.departure_mode() == DepartureMode::Earliest {
+            return window.earliest;
+        }
+
+        let evaluation = self.evaluate_route(path, window.earliest);
+        let mut waited = TimeDelta::zero();
+        let mut slack: Option<TimeDelta> = None;
+        for stop in &evaluation.schedule {
+            let Some(cust) = self.customer(&stop.node) else { continue };
+            waited += stop.service_start - stop.arrival;
+            let stop_slack = waited + (cust.window_end - stop.service_end.with_timezone(&Utc));
+            slack = Some(slack.map_or(stop_slack, |slack| slack.min(stop_slack)));
+        }
+        let Some(slack) = slack else {
+            return window.earliest;
+        };
+
+        let mut delay = match self.departure_mode() {
+            DepartureMode::LatestFeasible => slack,
+            _ => slack.min(waited),
+        };
+        if let Some(latest) = window.latest {
+            delay = delay.min(latest - window.earliest);
+        }
+        let delay = delay.max(TimeDelta::zero());
+        if !self.travel().is_time_dependent() {
+            return window.earliest + delay;
+        }
+
+        // Travel times change with the departure, so the slack is only an
+        // estimate. Arrivals are FIFO, so a route that breaks a window at some
+        // delay breaks it at any longer one and the largest safe delay can be
+        // bisected.
+        let allowed = evaluation.violations.len();
+        let fits = |secs: i64| {
+            self.evaluate_route(path, window.earliest + TimeDelta::seconds(secs)).violations.len() <= allowed
+        };
+        let (mut fitting, mut breaking) = (0, delay.num_seconds());
+        if fits(breaking) {
+            return window.earliest + delay;
+        }
+        while breaking - fitting > 1 {
+            let mid = fitting + (breaking - fitting) / 2;
+            if fits(mid) { fitting = mid } else { breaking = mid }
+        }
+        window.earliest + TimeDelta::seconds(fitting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveTime, TimeDelta, Utc};
+    use crate::models::{DepartureMode, SpeedPeriod, SpeedProfile};
+    use crate::pricing::PricingProblem;
+    use crate::{build_pricing, test_input};
+
+    fn pricing(mode: DepartureMode, profile: Option<SpeedProfile>) -> PricingProblem {
+        let mut input = test_input();
+        input.departure_mode = mode;
+        input.speed_profile = profile;
+        build_pricing(input).unwrap()
+    }
+
+    fn nodes(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    /// (slack, total waiting) of `path` leaving at its earliest departure.
+    fn slack_and_waiting(pricing: &PricingProblem, path: &[String]) -> (TimeDelta, TimeDelta) {
+        let schedule = pricing.evaluate_route(path, pricing.departure_from(&path[0])).schedule;
+        let mut waited = TimeDelta::zero();
+        let mut slack = TimeDelta::MAX;
+        for stop in &schedule {
+            let Some(cust) = pricing.customer(&stop.node) else { continue };
+            waited += TimeDelta::seconds((stop.waiting_minutes * 60.0) as i64);
+            slack = slack.min(waited + (cust.window_end - stop.service_end.with_timezone(&Utc)));
+        }
+        (slack, waited)
+    }
+
+    #[test]
+    fn earliest_leaves_at_the_start_of_the_window() {
+        let pricing = pricing(DepartureMode::Earliest, None);
+        let path = nodes(&["W_1", "C_2", "C_3", "W_1"]);
+
+        assert_eq!(pricing.choose_departure(&path), pricing.departure_from("W_1"));
+    }
+
+    #[test]
+    fn latest_feasible_uses_up_the_slack() {
+        let pricing = pricing(DepartureMode::LatestFeasible, None);
+        let path = nodes(&["W_1", "C_2", "C_3", "W_1"]);
+        let earliest = pricing.departure_from("W_1");
+        let (slack, _) = slack_and_waiting(&pricing, &path);
+
+        let departure = pricing.choose_departure(&path);
+
+        assert!(slack > TimeDelta::zero());
+        assert_eq!(departure, earliest + slack);
+        assert!(pricing.evaluate_route(&path, departure).is_feasible());
+        assert!(!pricing.evaluate_route(&path, departure + TimeDelta::minutes(1)).is_feasible());
+    }
+
+    #[test]
+    fn min_waiting_stops_once_the_waiting_is_gone() {
+        let pricing = pricing(DepartureMode::MinWaiting, None);
+        let path = nodes(&["W_1", "C_2", "C_3", "W_1"]);
+        let earliest = pricing.departure_from("W_1");
+        let (slack, waited) = slack_and_waiting(&pricing, &path);
+
+        let departure = pricing.choose_departure(&path);
+        let evaluation = pricing.evaluate_route(&path, departure);
+
+        assert!(waited < slack);
+        assert_eq!(departure, earliest + waited);
+        assert!(evaluation.is_feasible());
+        assert_eq!(evaluation.schedule.iter().map(|stop| stop.waiting_minutes).sum::<f64>(), 0.0);
+    }
+
+    #[test]
+    fn time_dependent_departure_is_bisected_to_the_last_feasible_second() {
+        // Crawling from 10:00 makes the constant-speed slack far too optimistic
+        let periods = [(0, 50.0), (10, 1.0)].iter()
+            .map(|&(hour, speed_kmh)| SpeedPeriod { start: NaiveTime::from_hms_opt(hour, 0, 0).unwrap(), speed_kmh })
+            .collect();
+        let pricing = pricing(DepartureMode::LatestFeasible, Some(SpeedProfile { periods, zones: Vec::new() }));
+        let path = nodes(&["W_1", "C_2", "W_1"]);
+        let earliest = pricing.departure_from("W_1");
+        let (slack, _) = slack_and_waiting(&pricing, &path);
+
+        let departure = pricing.choose_departure(&path);
+
+        assert!(departure > earliest);
+        assert!(departure < earliest + slack);
+        assert!(pricing.evaluate_route(&path, departure).is_feasible());
+        assert!(!pricing.evaluate_route(&path, departure + TimeDelta::seconds(1)).is_feasible());
+    }
+}
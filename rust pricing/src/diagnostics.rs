@@ -0,0 +1,134 @@
+use crate::models::{CustomerDiagnostic, InfeasibilityReason, InfeasibilityReport, ReasonKind};
+use crate::pricing::PricingProblem;
+use crate::travel_time::minutes;
+
+impl PricingProblem {
+    /// Customers that no route can visit, and why.
+    ///
+    /// A customer is out if its demand exceeds `max_capacity`, its window
+    /// cannot hold one service, or no warehouse reaches it in time on a
+    /// direct leg at its departure. Anything else can be visited
+    /// by a one-customer route, so this is exact for the labeling's rules.
+    pub fn infeasibility_report(&self) -> InfeasibilityReport {
+        let service = self.service_duration();
+
+        // Natural order, C_2 before C_10
+        let mut nodes: Vec<&str> = self.customer_ids().collect();
+        nodes.sort_by_key(|node| (node.len(), *node));
+        let mut warehouses: Vec<&str> = self.warehouse_ids().collect();
+        warehouses.sort_by_key(|node| (node.len(), *node));
+
+        let mut unreachable = Vec::new();
+        for node in &nodes {
+            let cust = self.customer(node).expect("listed customer exists");
+            let mut reasons = Vec::new();
+
+            if cust.capacity > self.max_capacity() {
+                reasons.push(InfeasibilityReason {
+                    kind: ReasonKind::CapacityExceedsVehicle,
+                    warehouse: None,
+                    message: format!("Demand {} exceeds max capacity {}", cust.capacity, self.max_capacity()),
+                });
+            }
+
+            let window_minutes = minutes(cust.window_end - cust.window_start);
+            let window_fits = cust.window_start + service <= cust.window_end;
+            if !window_fits {
+                reasons.push(InfeasibilityReason {
+                    kind: ReasonKind::WindowTooShort,
+                    warehouse: None,
+                    message: format!(
+                        "Window of {} minutes is shorter than the service time of {} minutes",
+                        window_minutes, minutes(service)
+                    ),
+                });
+            }
+
+            let late: Vec<InfeasibilityReason> = warehouses.iter().filter_map(|wh| {
+                let edge = self.edge(wh, node)?;
+                let departure = self.departure_from(wh);
+                let arrival = self.arrival(edge, departure);
+                let service_end = arrival.max(cust.window_start) + service;
+                (service_end > cust.window_end).then(|| InfeasibilityReason {
+                    kind: ReasonKind::UnreachableFromWarehouse,
+                    warehouse: Some(wh.to_string()),
+                    message: format!(
+                        "Leaving {} at {} arrives at {}, service would end at {} after the window closes at {}",
+                        wh,
+                        self.local_time(departure).format("%H:%M"),
+                        self.local_time(arrival).format("%H:%M"),
+                        self.local_time(service_end).format("%H:%M"),
+                        self.local_time(cust.window_end).format("%H:%M"),
+                    ),
+                })
+            }).collect();
+            // Late from some warehouses is fine as long as one is in time. A
+            // window too short for service is late from everywhere anyway.
+            if window_fits && !late.is_empty() && late.len() == warehouses.len() {
+                reasons.extend(late);
+            }
+
+            if !reasons.is_empty() {
+                unreachable.push(CustomerDiagnostic { node: node.to_string(), reasons });
+            }
+        }
+
+        InfeasibilityReport { customers: nodes.len(), unreachable }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use crate::models::{InfeasibilityReport, InputData, ReasonKind};
+    use crate::{build_pricing, test_input};
+
+    fn report(input: InputData) -> InfeasibilityReport {
+        build_pricing(input).unwrap().infeasibility_report()
+    }
+
+    /// (node, kind, warehouse) of every reason, in report order.
+    fn reasons(report: &InfeasibilityReport) -> Vec<(String, ReasonKind, Option<String>)> {
+        report.unreachable.iter()
+            .flat_map(|cust| cust.reasons.iter().map(|r| (cust.node.clone(), r.kind, r.warehouse.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn fixture_customers_are_all_reachable() {
+        let report = report(test_input());
+
+        assert_eq!(report.customers, 20);
+        assert!(report.unreachable.is_empty());
+    }
+
+    #[test]
+    fn demand_above_capacity_and_short_windows() {
+        let mut input = test_input();
+        input.customers[0].capacity = 600.0;
+        input.customers[1].window_end = input.customers[1].window_start + TimeDelta::minutes(10);
+
+        assert_eq!(reasons(&report(input)), [
+            ("C_1".to_string(), ReasonKind::CapacityExceedsVehicle, None),
+            ("C_2".to_string(), ReasonKind::WindowTooShort, None),
+        ]);
+    }
+
+    #[test]
+    fn window_closing_before_any_warehouse_arrives() {
+        let mut input = test_input();
+        // The vehicles leave at 08:00, the window closes at 08:05
+        let departure = input.customers[0].window_start;
+        input.customers[2].window_start = departure - TimeDelta::hours(1);
+        input.customers[2].window_end = departure + TimeDelta::minutes(5);
+
+        let reasons = reasons(&report(input));
+
+        assert_eq!(reasons.len(), 3, "one reason per warehouse: {:?}", reasons);
+        for (node, kind, warehouse) in &reasons {
+            assert_eq!(node, "C_3");
+            assert_eq!(*kind, ReasonKind::UnreachableFromWarehouse);
+            assert!(warehouse.is_some());
+        }
+    }
+}
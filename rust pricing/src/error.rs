@@ -0,0 +1,120 @@
+use serde_json::json;
+use std::io;
+use std::time::Duration;
+use thiserror::Error;
+use crate::models::{ConsistencyReport, ValidationReport};
+
+/// Everything that can make a command fail, by what the caller should do
+/// about it. Each kind has its own process exit code:
+///
+/// | code | kind          |
+/// |------|---------------|
+/// | 0    | success       |
+/// | 2    | usage         |
+/// | 3    | parse         |
+/// | 4    | validation    |
+/// | 5    | evaluator     |
+/// | 6    | limit         |
+/// | 7    | consistency   |
+/// | 8    | io            |
+#[derive(Debug, Error)]
+pub enum PricingError {
+    /// Command line arguments that do not fit together.
+    #[error("{0}")]
+    Usage(String),
+    /// Input that is not valid JSON for `InputData`, or a malformed route file.
+    #[error("Could not parse input: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// Input that parses but describes an impossible instance.
+    #[error("Input has {} problem(s)", .0.issues.len())]
+    Validation(ValidationReport),
+    /// The trip cost evaluator failed for every improving route.
+    #[error("Trip cost evaluator failed: {0}")]
+    Evaluator(String),
+    /// The search ran into its time limit.
+    #[error("{0}")]
+    Limit(String),
+    /// Edge and evaluator cost of the returned route differ by more than the tolerance.
+    #[error("Edge cost {:.3} and evaluator cost {:.3} differ by {:.3}, more than the tolerance {}",
+        .0.edge_cost, .0.evaluator_cost, .0.difference, .0.tolerance)]
+    Consistency(Box<ConsistencyReport>),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl PricingError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PricingError::Usage(_) => 2,
+            PricingError::Parse(_) => 3,
+            PricingError::Validation(_) => 4,
+            PricingError::Evaluator(_) => 5,
+            PricingError::Limit(_) => 6,
+            PricingError::Consistency(_) => 7,
+            PricingError::Io(_) => 8,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PricingError::Usage(_) => "usage",
+            PricingError::Parse(_) => "parse",
+            PricingError::Validation(_) => "validation",
+            PricingError::Evaluator(_) => "evaluator",
+            PricingError::Limit(_) => "limit",
+            PricingError::Consistency(_) => "consistency",
+            PricingError::Io(_) => "io",
+        }
+    }
+
+    /// Error object printed on stdout with `--json-errors`. `details` holds
+    /// the validation or consistency report where there is one.
+    pub fn to_json(&self) -> serde_json::Value {
+        let details = match self {
+            PricingError::Validation(report) => json!(report),
+            PricingError::Consistency(report) => json!(report),
+            _ => serde_json::Value::Null,
+        };
+        json!({
+            "error": {
+                "kind": self.kind(),
+                "exit_code": self.exit_code(),
+                "message": self.to_string(),
+                "details": details,
+            }
+        })
+    }
+}
+
+/// Why a trip cost evaluator could not cost one route. Unlike
+/// `PricingError` it does not end the run: the route is skipped or falls
+/// back to its edge cost.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum EvaluatorError {
+    #[error("Unknown location {0}")]
+    UnknownLocation(String),
+    #[error("Customer {0} has no time window")]
+    MissingWindow(String),
+    /// The calculator could not be started or waited for.
+    #[error("{program}: {message}")]
+    Process { program: String, message: String },
+    #[error("Calculator timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Calculator error ({status}): {stderr}")]
+    Failed { status: String, stderr: String },
+    #[error("Invalid calculator output: {0}")]
+    InvalidOutput(String),
+    /// An `{"error": ..}` answer of a JSON-lines session.
+    #[error("Calculator error: {0}")]
+    Reported(String),
+    #[error("Calculator session closed")]
+    SessionClosed,
+    #[error("Calculator disabled after {0} consecutive failures")]
+    Disabled(usize),
+}
+
+impl From<csv::Error> for PricingError {
+    fn from(e: csv::Error) -> Self {
+        PricingError::Io(e.into())
+    }
+}
@@ -0,0 +1,387 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use crate::error::PricingError;
+use crate::models::{CostBreakdown, CostSource, DepartureMode, DualContribution, EvaluatedCost, RouteReport, StopSchedule, Violation, ViolationKind};
+use crate::pricing::PricingProblem;
+use crate::travel_time::minutes;
+use crate::trip_cost::TripCost;
+
+/// Reduced cost, cost breakdown, load, schedule and constraint check of a
+/// concrete customer sequence, recomputed from the arc data rather than
+/// carried over from the labeling.
+#[derive(Debug, Clone)]
+pub struct RouteEvaluation {
+    pub reduced_cost: f64,
+    pub load: f64,
+    pub schedule: Vec<StopSchedule>,
+    pub breakdown: CostBreakdown,
+    pub violations: Vec<Violation>,
+}
+
+impl RouteEvaluation {
+    pub fn is_feasible(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl PricingProblem {
+    /// Checks routes given from outside, e.g. edited by hand, each leaving
+    /// its warehouse as `departure_mode` says. Their trip costs come from the
+    /// evaluator in one batch; a route it cannot cost reports its edge cost
+    /// instead. A route of fewer than two nodes is not a route at all and
+    /// fails the whole call.
+    pub fn evaluate_paths(&self, paths: &[Vec<String>]) -> Result<Vec<RouteReport>, PricingError> {
+        if let Some((index, path)) = paths.iter().enumerate().find(|(_, path)| path.len() < 2) {
+            return Err(PricingError::Usage(format!(
+                "Route {} has {} node(s), a route needs at least two", index, path.len()
+            )));
+        }
+        let routes: Vec<_> = paths.iter()
+            .map(|path| (path.clone(), self.choose_departure(path)))
+            .collect();
+        let trips = self.evaluator().evaluate_batch(&routes);
+
+        Ok(routes.iter().zip(trips).map(|((path, departure), trip)| {
+            let mut evaluation = self.evaluate_route(path, *departure);
+            evaluation.breakdown.evaluated = Some(self.evaluated_cost(path, &evaluation.breakdown, trip.as_ref().ok()));
+            let (cost, cost_source, evaluator_error) = match trip {
+                Ok(trip) => (trip.total_cost, CostSource::Evaluator, None),
+                Err(e) => (evaluation.breakdown.distance_cost, CostSource::EdgeCost, Some(e.to_string())),
+            };
+
+            RouteReport {
+                path: path.clone(),
+                feasible: evaluation.is_feasible(),
+                reduced_cost: evaluation.reduced_cost,
+                cost,
+                cost_source,
+                distance_metric: self.distance_metric(),
+                evaluator_error,
+                capacity: evaluation.load,
+                violations: evaluation.violations,
+                schedule: evaluation.schedule,
+                cost_breakdown: evaluation.breakdown,
+            }
+        }).collect())
+    }
+
+    /// Evaluates a closed route `W_x, C_.., W_x` leaving at `departure`,
+    /// using the same travel times, service time and window rules as the
+    /// labeling.
+    pub(crate) fn evaluate_route(&self, path: &[String], departure: DateTime<Utc>) -> RouteEvaluation {
+        let mut violations = Vec::new();
+        let mut violate = |kind: ViolationKind, node: Option<&str>, message: String| {
+            violations.push(Violation { kind, node: node.map(str::to_string), message });
+        };
+
+        let start = path.first().map(String::as_str).unwrap_or_default();
+        let end = path.last().map(String::as_str).unwrap_or_default();
+        if self.warehouse(start).is_none() {
+            violate(ViolationKind::StartWarehouse, Some(start), format!("Route must start at a warehouse, not '{}'", start));
+        }
+        if path.len() < 2 || end != start {
+            violate(ViolationKind::ReturnWarehouse, Some(end), format!("Route must return to its start warehouse {}", start));
+        }
+
+        let mut schedule = Vec::with_capacity(path.len());
+        if !start.is_empty() {
+            let at = self.local_time(departure);
+            schedule.push(StopSchedule {
+                node: start.to_string(),
+                arrival: at,
+                waiting_minutes: 0.0,
+                service_start: at,
+                service_end: at,
+                departure: at,
+                load: 0.0,
+                distance_km: 0.0,
+                late_arrival_minutes: 0.0,
+                late_service_minutes: 0.0,
+            });
+        }
+
+        let mut seen = HashSet::new();
+        let mut load = 0.0;
+        let mut distance_km = 0.0;
+        let mut stops = 0;
+        let mut reduced_cost = 0.0;
+        let mut ready = departure;
+        // Past an unknown node there are no arcs and no times to check
+        let mut lost = self.customer(start).is_none() && self.warehouse(start).is_none();
+
+        for (k, pair) in path.windows(2).enumerate() {
+            let (u, v) = (pair[0].as_str(), pair[1].as_str());
+
+            if self.customer(v).is_none() && self.warehouse(v).is_none() {
+                violate(ViolationKind::UnknownNode, Some(v), format!("Unknown node {}", v));
+                lost = true;
+                continue;
+            }
+            if self.warehouse(v).is_some() && k + 2 < path.len() {
+                violate(ViolationKind::ReturnWarehouse, Some(v), format!("Route passes warehouse {} before its end", v));
+            }
+            if self.customer(v).is_some() && !seen.insert(v) {
+                violate(ViolationKind::DuplicateCustomer, Some(v), format!("{} is visited more than once", v));
+            }
+            if lost {
+                continue;
+            }
+
+            match self.edge(u, v) {
+                Some(edge) => {
+                    reduced_cost += edge.reduced_cost;
+                    distance_km += edge.distance_km;
+                    ready = self.arrival(edge, ready);
+                }
+                None => {
+                    violate(ViolationKind::MissingArc, Some(v), format!("No arc from {} to {}", u, v));
+                    continue;
+                }
+            }
+
+            let arrival = ready;
+            let Some(cust) = self.customer(v) else {
+                schedule.push(StopSchedule {
+                    node: v.to_string(),
+                    arrival: self.local_time(arrival),
+                    waiting_minutes: 0.0,
+                    service_start: self.local_time(arrival),
+                    service_end: self.local_time(arrival),
+                    departure: self.local_time(arrival),
+                    load,
+                    distance_km,
+                    late_arrival_minutes: 0.0,
+                    late_service_minutes: 0.0,
+                });
+                continue;
+            };
+
+            stops += 1;
+
+            load += cust.capacity;
+            if load > self.max_capacity() {
+                violate(ViolationKind::Capacity, Some(v), format!(
+                    "Load after {} exceeds max capacity ({} > {})", v, load, self.max_capacity()
+                ));
+            }
+
+            if arrival > cust.window_end {
+                violate(ViolationKind::LateArrival, Some(v), format!(
+                    "Arrival at {} after window ({} > {})", v, self.local_time(arrival).to_rfc3339(),
+                    self.local_time(cust.window_end).to_rfc3339()
+                ));
+            }
+
+            let service_start = arrival.max(cust.window_start);
+            let service_end = service_start + self.service_duration();
+            if service_end > cust.window_end {
+                violate(ViolationKind::LateService, Some(v), format!(
+                    "Service at {} exceeds window ({} > {})", v, self.local_time(service_end).to_rfc3339(),
+                    self.local_time(cust.window_end).to_rfc3339()
+                ));
+            }
+
+            schedule.push(StopSchedule {
+                node: v.to_string(),
+                arrival: self.local_time(arrival),
+                waiting_minutes: minutes(service_start - arrival),
+                service_start: self.local_time(service_start),
+                service_end: self.local_time(service_end),
+                departure: self.local_time(service_end),
+                load,
+                distance_km,
+                late_arrival_minutes: minutes(arrival - cust.window_end),
+                late_service_minutes: minutes(service_end - cust.window_end),
+            });
+            ready = service_end;
+        }
+
+        if stops > self.max_stops() {
+            violate(ViolationKind::MaxStops, None, format!(
+                "Route visits {} customers, more than max stops {}", stops, self.max_stops()
+            ));
+        }
+
+        let breakdown = self.cost_breakdown(path, &schedule);
+        if self.departure_mode() != DepartureMode::Earliest {
+            reduced_cost += breakdown.waiting_penalty;
+        }
+        RouteEvaluation { reduced_cost, load, schedule, breakdown, violations }
+    }
+
+    fn cost_breakdown(&self, path: &[String], schedule: &[StopSchedule]) -> CostBreakdown {
+        let penalties = self.penalties();
+        let minutes_total = |f: fn(&StopSchedule) -> f64| schedule.iter().map(f).sum::<f64>();
+
+        let distance_cost = self.calculate_path_cost(path);
+        let fixed_vehicle_cost = self.fixed_vehicle_cost();
+        let waiting_penalty = penalties.waiting_per_minute * minutes_total(|s| s.waiting_minutes);
+        let late_arrival_penalty = penalties.late_arrival_per_minute * minutes_total(|s| s.late_arrival_minutes);
+        let late_service_penalty = penalties.late_service_per_minute * minutes_total(|s| s.late_service_minutes);
+        // With a chosen departure, waiting is part of what the route costs
+        let priced_waiting = if self.departure_mode() == DepartureMode::Earliest { 0.0 } else { waiting_penalty };
+        let priced_cost = distance_cost + priced_waiting;
+
+        let duals: Vec<DualContribution> = path.iter()
+            .filter(|node| self.customer(node).is_some())
+            .map(|node| DualContribution { node: node.clone(), dual: self.dual(node) })
+            .collect();
+
+        CostBreakdown {
+            distance_cost,
+            fixed_vehicle_cost,
+            waiting_penalty,
+            late_arrival_penalty,
+            late_service_penalty,
+            priced_cost,
+            total_cost: distance_cost + fixed_vehicle_cost + waiting_penalty + late_arrival_penalty + late_service_penalty,
+            dual_total: duals.iter().map(|d| d.dual).sum(),
+            duals,
+            evaluated: None,
+        }
+    }
+
+    /// Breakdown of `cost`: the evaluator's `trip`, or the graph's edges when
+    /// the cost fell back to them.
+    pub(crate) fn evaluated_cost(&self, path: &[String], breakdown: &CostBreakdown, trip: Option<&TripCost>) -> EvaluatedCost {
+        match trip {
+            Some(trip) => {
+                let distance_cost = self.cost_per_km() * trip.total_distance;
+                EvaluatedCost {
+                    distance_km: trip.total_distance,
+                    distance_cost,
+                    other_cost: trip.total_cost - distance_cost,
+                    waiting_penalty: trip.total_waiting_penalty,
+                    time_window_penalty: trip.total_time_window_penalty,
+                }
+            }
+            None => EvaluatedCost {
+                distance_km: path.windows(2)
+                    .filter_map(|pair| self.edge(&pair[0], &pair[1]))
+                    .map(|edge| edge.distance_km)
+                    .sum(),
+                distance_cost: breakdown.distance_cost,
+                other_cost: 0.0,
+                waiting_penalty: breakdown.waiting_penalty,
+                time_window_penalty: breakdown.late_arrival_penalty + breakdown.late_service_penalty,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::PricingError;
+    use crate::models::ViolationKind;
+    use crate::{build_pricing, test_input};
+
+    fn route(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn paths_of_fewer_than_two_nodes_are_rejected() {
+        let pricing = build_pricing(test_input()).unwrap();
+
+        for short in [route(&[]), route(&["W_1"])] {
+            let result = pricing.evaluate_paths(&[route(&["W_1", "C_1", "W_1"]), short]);
+            assert!(matches!(result, Err(PricingError::Usage(ref message)) if message.starts_with("Route 1 ")), "{:?}", result);
+        }
+        assert_eq!(pricing.evaluate_paths(&[route(&["W_1", "W_1"])]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn arcs_are_not_checked_past_an_unknown_node() {
+        let pricing = build_pricing(test_input()).unwrap();
+        let kinds = |path: &[&str]| -> Vec<ViolationKind> {
+            let path = route(path);
+            let departure = pricing.departure_from(&path[0]);
+            pricing.evaluate_route(&path, departure).violations.iter().map(|v| v.kind).collect()
+        };
+
+        assert_eq!(kinds(&["W_1", "C_404", "C_1", "W_1"]), [ViolationKind::UnknownNode]);
+        assert_eq!(kinds(&["X", "C_1", "X"]), [
+            ViolationKind::StartWarehouse,
+            ViolationKind::UnknownNode,
+        ]);
+    }
+
+    #[test]
+    fn fixed_vehicle_cost_is_reported_but_not_priced() {
+        let path = route(&["W_1", "C_1", "C_2", "W_1"]);
+        let without = build_pricing(test_input()).unwrap();
+        let mut input = test_input();
+        input.fixed_vehicle_cost = 100.0;
+        let with = build_pricing(input).unwrap();
+
+        let departure = without.departure_from("W_1");
+        let (plain, charged) = (without.evaluate_route(&path, departure), with.evaluate_route(&path, departure));
+
+        assert_eq!(charged.reduced_cost, plain.reduced_cost);
+        let breakdown = &charged.breakdown;
+        assert_eq!(breakdown.fixed_vehicle_cost, 100.0);
+        assert_eq!(breakdown.priced_cost, plain.breakdown.priced_cost);
+        assert!((breakdown.total_cost - plain.breakdown.total_cost - 100.0).abs() < 1e-9);
+        assert!((breakdown.priced_cost - breakdown.dual_total - charged.reduced_cost).abs() < 1e-9);
+
+        // Nor does the labeling price it.
+        let small = |fixed_vehicle_cost| {
+            let mut input = test_input();
+            input.customers.truncate(6);
+            input.fixed_vehicle_cost = fixed_vehicle_cost;
+            build_pricing(input).unwrap().find_negative_path(None).unwrap().unwrap().reduced_cost
+        };
+        assert_eq!(small(100.0), small(0.0));
+    }
+
+    #[test]
+    fn schedule_and_reduced_cost_follow_the_given_order() {
+        let pricing = build_pricing(test_input()).unwrap();
+        let departure = pricing.departure_from("W_1");
+
+        for path in [route(&["W_1", "C_1", "C_2", "W_1"]), route(&["W_1", "C_2", "C_1", "W_1"])] {
+            let evaluation = pricing.evaluate_route(&path, departure);
+            let edges: Vec<_> = path.windows(2).map(|pair| pricing.edge(&pair[0], &pair[1]).unwrap()).collect();
+
+            let edge_cost: f64 = edges.iter().map(|edge| edge.reduced_cost).sum();
+            assert!((evaluation.reduced_cost - edge_cost).abs() < 1e-9);
+            assert_eq!(evaluation.load, 8.0);
+            let nodes: Vec<&str> = evaluation.schedule.iter().map(|stop| stop.node.as_str()).collect();
+            assert_eq!(nodes, path.iter().map(String::as_str).collect::<Vec<_>>());
+
+            let mut load = 0.0;
+            for stop in &evaluation.schedule {
+                let Some(cust) = pricing.customer(&stop.node) else { continue };
+                load += cust.capacity;
+                assert_eq!(stop.load, load);
+                assert!(stop.service_start >= cust.window_start);
+                assert_eq!(stop.service_end - stop.service_start, pricing.service_duration());
+            }
+            let distance: f64 = edges.iter().map(|edge| edge.distance_km).sum();
+            assert!((evaluation.schedule.last().unwrap().distance_km - distance).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn broken_constraints_are_reported() {
+        let mut input = test_input();
+        input.max_capacity = 10.0;
+        input.max_stops = 1;
+        let pricing = build_pricing(input).unwrap();
+        let departure = pricing.departure_from("W_1");
+        let kinds = |path: &[&str]| -> Vec<ViolationKind> {
+            pricing.evaluate_route(&route(path), departure).violations.iter().map(|v| v.kind).collect()
+        };
+
+        assert!(kinds(&["W_1", "C_1", "W_1"]).is_empty());
+        // C_3 is served from 10:00, past the end of C_1's window
+        assert_eq!(kinds(&["W_1", "C_3", "C_1", "W_1"]), [
+            ViolationKind::Capacity,
+            ViolationKind::LateArrival,
+            ViolationKind::LateService,
+            ViolationKind::MaxStops,
+        ]);
+        assert!(kinds(&["W_1", "C_1", "W_2", "C_2", "W_1"]).contains(&ViolationKind::ReturnWarehouse));
+        assert!(kinds(&["W_1", "C_1", "W_2"]).contains(&ViolationKind::ReturnWarehouse));
+    }
+}
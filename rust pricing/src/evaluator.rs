@@ -0,0 +1,544 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::error::EvaluatorError;
+use crate::models::{CacheStats, CalculatorProtocol, EvaluatorConfig, RouteCacheConfig};
+use crate::route_cache::CachedEvaluator;
+use crate::trip_cost::{calculator_route, TripCost, TripCostModel};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Consecutive calculator failures after which it is no longer started.
+const MAX_CONSECUTIVE_FAILURES: usize = 5;
+
+/// A route to cost and the time its vehicle leaves the warehouse.
+pub type RouteRequest = (Vec<String>, DateTime<Utc>);
+
+/// Costs finished routes. `PricingProblem` hands over all of its improving
+/// routes at once, so the cost model can be swapped without touching the
+/// labeling.
+pub trait TripCostEvaluator {
+    fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError>;
+
+    /// One result per route, in order. Backends with a per-call overhead
+    /// override this to share it across the batch.
+    fn evaluate_batch(&self, routes: &[RouteRequest]) -> Vec<Result<TripCost, EvaluatorError>> {
+        routes.iter().map(|(path, departure)| self.evaluate(path, *departure)).collect()
+    }
+
+    /// Hits and misses so far, for evaluators with a cache.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+}
+
+impl TripCostEvaluator for TripCostModel {
+    fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+        TripCostModel::evaluate(self, path, departure)
+    }
+}
+
+/// Runs an external trip calculator, either once per route or as one
+/// long-lived JSON-lines session (see `CalculatorProtocol`).
+pub struct SubprocessEvaluator {
+    model: TripCostModel,
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Option<Duration>,
+    protocol: CalculatorProtocol,
+    session: RefCell<Option<Session>>,
+    consecutive_failures: Cell<usize>,
+}
+
+impl SubprocessEvaluator {
+    pub fn new(
+        model: TripCostModel,
+        program: PathBuf,
+        args: Vec<String>,
+        timeout: Option<Duration>,
+        protocol: CalculatorProtocol,
+    ) -> Self {
+        Self {
+            model,
+            program,
+            args,
+            timeout,
+            protocol,
+            session: RefCell::new(None),
+            consecutive_failures: Cell::new(0),
+        }
+    }
+
+    /// Once the calculator has failed too often in a row, every further route
+    /// fails straight away instead of waiting out another timeout.
+    fn disabled(&self) -> Option<EvaluatorError> {
+        let failures = self.consecutive_failures.get();
+        (failures >= MAX_CONSECUTIVE_FAILURES).then_some(EvaluatorError::Disabled(failures))
+    }
+
+    fn record(&self, result: Result<TripCost, EvaluatorError>) -> Result<TripCost, EvaluatorError> {
+        match result {
+            Ok(_) => self.consecutive_failures.set(0),
+            Err(_) => self.consecutive_failures.set(self.consecutive_failures.get() + 1),
+        }
+        result
+    }
+
+    fn run_once(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+        let input = self.model.calculator_input(path, departure).to_string();
+
+        let mut child = calculator_command(&self.program, &self.args)
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| process_error(&self.program, e))?;
+
+        // Feed and drain the pipes on their own threads, so a calculator that
+        // writes a lot before reading all of its input cannot deadlock us.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+
+        // After a timeout the readers are left behind: something outside the
+        // process group may still hold the pipes open.
+        let status = wait_with_timeout(&mut child, &self.program, self.timeout)?;
+        // A calculator that exits without reading everything breaks the pipe;
+        // its exit status is what counts.
+        let _ = writer.join();
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            return Err(EvaluatorError::Failed {
+                status: status.to_string(),
+                stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+            });
+        }
+
+        serde_json::from_slice(&stdout).map_err(|e| EvaluatorError::InvalidOutput(e.to_string()))
+    }
+
+    /// Sends the whole batch down the session before reading any answer. If
+    /// the session breaks, it is dropped and the unanswered routes fail.
+    fn run_session(&self, routes: &[RouteRequest]) -> Vec<Result<TripCost, EvaluatorError>> {
+        let mut slot = self.session.borrow_mut();
+        if slot.is_none() {
+            match Session::start(&self.program, &self.args, &self.model) {
+                Ok(session) => *slot = Some(session),
+                Err(e) => return routes.iter().map(|_| Err(e.clone())).collect(),
+            }
+        }
+        let session = slot.as_mut().expect("session was just started");
+        let sent = routes.iter().try_for_each(|(path, departure)| {
+            session.send(calculator_route(path, *departure).to_string())
+        });
+        if let Err(e) = sent {
+            *slot = None;
+            return routes.iter().map(|_| Err(e.clone())).collect();
+        }
+
+        let mut results = Vec::with_capacity(routes.len());
+        while results.len() < routes.len() {
+            let received = slot.as_mut().expect("session is open").receive(self.timeout);
+            match received {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    *slot = None;
+                    results.resize(routes.len(), Err(e));
+                }
+            }
+        }
+        results
+    }
+}
+
+impl TripCostEvaluator for SubprocessEvaluator {
+    fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+        self.evaluate_batch(&[(path.to_vec(), departure)])
+            .pop()
+            .expect("one result per route")
+    }
+
+    fn evaluate_batch(&self, routes: &[RouteRequest]) -> Vec<Result<TripCost, EvaluatorError>> {
+        match self.protocol {
+            CalculatorProtocol::PerRoute => routes.iter()
+                .map(|(path, departure)| match self.disabled() {
+                    Some(e) => Err(e),
+                    None => self.record(self.run_once(path, *departure)),
+                })
+                .collect(),
+            CalculatorProtocol::JsonLines => match self.disabled() {
+                Some(e) => routes.iter().map(|_| Err(e.clone())).collect(),
+                None => self.run_session(routes)
+                    .into_iter()
+                    .map(|result| self.record(result))
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Calculator process speaking the JSON-lines protocol. Requests and
+/// responses go through threads so a stuck calculator can be timed out.
+struct Session {
+    child: Child,
+    requests: Option<Sender<String>>,
+    responses: Receiver<String>,
+}
+
+/// Answer to one route request of a session.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SessionResponse {
+    Error { error: String },
+    Trip(Box<TripCost>),
+}
+
+impl Session {
+    fn start(program: &PathBuf, args: &[String], model: &TripCostModel) -> Result<Self, EvaluatorError> {
+        let mut child = calculator_command(program, args)
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| process_error(program, e))?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let (requests, pending) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for line in pending {
+                if writeln!(stdin, "{}", line).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (answers, responses) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if answers.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut session = Self { child, requests: Some(requests), responses };
+        session.send(model.calculator_instance().to_string())?;
+        Ok(session)
+    }
+
+    fn send(&mut self, line: String) -> Result<(), EvaluatorError> {
+        self.requests.as_ref()
+            .and_then(|requests| requests.send(line).ok())
+            .ok_or(EvaluatorError::SessionClosed)
+    }
+
+    /// Next answer; the outer error means the session itself broke.
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Result<TripCost, EvaluatorError>, EvaluatorError> {
+        let line = match timeout {
+            Some(timeout) => self.responses.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => EvaluatorError::Timeout(timeout),
+                RecvTimeoutError::Disconnected => EvaluatorError::SessionClosed,
+            })?,
+            None => self.responses.recv().map_err(|_| EvaluatorError::SessionClosed)?,
+        };
+
+        Ok(match serde_json::from_str(&line) {
+            Ok(SessionResponse::Trip(trip)) => Ok(*trip),
+            Ok(SessionResponse::Error { error }) => Err(EvaluatorError::Reported(error)),
+            Err(e) => Err(EvaluatorError::InvalidOutput(e.to_string())),
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Closing stdin ends the session; the kill is for calculators that ignore it.
+        self.requests = None;
+        kill_group(&mut self.child);
+    }
+}
+
+/// The calculator with piped stdin and stdout. On Unix it leads its own
+/// process group, so a timeout also stops whatever a wrapper script started.
+fn calculator_command(program: &PathBuf, args: &[String]) -> Command {
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped()).stdout(Stdio::piped());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command
+}
+
+/// Kills the calculator together with its process group and reaps it.
+fn kill_group(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: kill(2) takes no pointers; a negative pid names the group the
+    // calculator was started as the leader of.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// The calculator could not be started or waited for.
+fn process_error(program: &Path, e: io::Error) -> EvaluatorError {
+    EvaluatorError::Process { program: program.display().to_string(), message: e.to_string() }
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Waits for the child, killing it once `timeout` has passed.
+fn wait_with_timeout(child: &mut Child, program: &Path, timeout: Option<Duration>) -> Result<ExitStatus, EvaluatorError> {
+    let Some(timeout) = timeout else {
+        return child.wait().map_err(|e| process_error(program, e));
+    };
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(|e| process_error(program, e))? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            kill_group(child);
+            return Err(EvaluatorError::Timeout(timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Deterministic stand-in for tests: a fixed cost per route plus a cost
+/// per customer, with no timing.
+pub struct MockEvaluator {
+    fixed_cost: f64,
+    cost_per_stop: f64,
+}
+
+impl MockEvaluator {
+    pub fn new(fixed_cost: f64, cost_per_stop: f64) -> Self {
+        Self { fixed_cost, cost_per_stop }
+    }
+}
+
+impl TripCostEvaluator for MockEvaluator {
+    fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+        let stops = path.iter().filter(|node| node.starts_with("C_")).count();
+        Ok(TripCost {
+            legs: Vec::new(),
+            total_distance: 0.0,
+            total_driving: 0.0,
+            total_waiting: 0.0,
+            total_service: 0.0,
+            total_cost: self.fixed_cost + self.cost_per_stop * stops as f64,
+            is_legal: true,
+            violations: Vec::new(),
+            departure_time: departure,
+            completion_time: departure,
+            total_waiting_penalty: 0.0,
+            total_time_window_penalty: 0.0,
+        })
+    }
+}
+
+/// Builds the evaluator selected by `config`, behind a route cache if one
+/// is enabled; `model` is the instance's in-process trip cost model, which
+/// also provides the subprocess input.
+pub fn build_evaluator(
+    config: &EvaluatorConfig,
+    cache: &RouteCacheConfig,
+    model: TripCostModel,
+) -> Box<dyn TripCostEvaluator> {
+    let evaluator = backend(config, model.clone());
+    if !cache.enabled && cache.file.is_none() {
+        return evaluator;
+    }
+    Box::new(CachedEvaluator::new(evaluator, model, config, cache.file.as_ref().map(PathBuf::from)))
+}
+
+fn backend(config: &EvaluatorConfig, model: TripCostModel) -> Box<dyn TripCostEvaluator> {
+    match config {
+        EvaluatorConfig::Internal => Box::new(model),
+        EvaluatorConfig::Subprocess { path, args, timeout_secs, protocol } => Box::new(SubprocessEvaluator::new(
+            model,
+            PathBuf::from(path),
+            args.clone(),
+            calculator_timeout(*timeout_secs),
+            *protocol,
+        )),
+        EvaluatorConfig::Mock { fixed_cost, cost_per_stop } => {
+            Box::new(MockEvaluator::new(*fixed_cost, *cost_per_stop))
+        }
+    }
+}
+
+/// The calculator timeout as a `Duration`. It is validated as finite and
+/// non-negative; one too long to represent means no timeout in practice.
+fn calculator_timeout(timeout_secs: Option<f64>) -> Option<Duration> {
+    timeout_secs.map(|secs| Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs;
+    use crate::test_trip_model;
+
+    /// A shell script in the temp dir. It is run through `/bin/sh` rather
+    /// than executed, so no test races another one's write of its file.
+    fn script(name: &str, body: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vrp_pricing_{}_{}.sh", name, std::process::id()));
+        fs::write(&path, body).unwrap();
+        path
+    }
+
+    fn calculator(script: &Path, protocol: CalculatorProtocol, timeout: Option<Duration>) -> SubprocessEvaluator {
+        let args = vec![script.display().to_string()];
+        SubprocessEvaluator::new(test_trip_model(), PathBuf::from("/bin/sh"), args, timeout, protocol)
+    }
+
+    fn route() -> RouteRequest {
+        let path = ["W_1", "C_1", "W_1"].iter().map(|id| id.to_string()).collect();
+        (path, Utc.with_ymd_and_hms(2025, 5, 17, 2, 0, 0).unwrap())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_calculator_is_killed_at_the_timeout() {
+        let path = script("slow", "sleep 30\n");
+        let timeout = Duration::from_millis(200);
+        let evaluator = calculator(&path, CalculatorProtocol::PerRoute, Some(timeout));
+
+        let started = Instant::now();
+        let (route, departure) = route();
+        let result = evaluator.evaluate(&route, departure);
+
+        assert_eq!(result.unwrap_err(), EvaluatorError::Timeout(timeout));
+        assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn malformed_output_and_failed_exits_are_errors() {
+        let garbage = script("garbage", "cat > /dev/null\necho not json\n");
+        let failing = script("failing", "cat > /dev/null\necho broken >&2\nexit 3\n");
+        let (route, departure) = route();
+
+        let result = calculator(&garbage, CalculatorProtocol::PerRoute, None).evaluate(&route, departure);
+        assert!(matches!(result, Err(EvaluatorError::InvalidOutput(_))), "{:?}", result);
+
+        let result = calculator(&failing, CalculatorProtocol::PerRoute, None).evaluate(&route, departure);
+        match result {
+            Err(EvaluatorError::Failed { stderr, .. }) => assert_eq!(stderr, "broken"),
+            other => panic!("{:?}", other),
+        }
+        fs::remove_file(&garbage).unwrap();
+        fs::remove_file(&failing).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn calculator_is_no_longer_started_after_repeated_failures() {
+        let log = std::env::temp_dir().join(format!("vrp_pricing_starts_{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let path = script("flaky", &format!("echo start >> '{}'\nexit 1\n", log.display()));
+        let evaluator = calculator(&path, CalculatorProtocol::PerRoute, None);
+
+        let results = evaluator.evaluate_batch(&vec![route(); MAX_CONSECUTIVE_FAILURES + 2]);
+
+        assert!(results[..MAX_CONSECUTIVE_FAILURES].iter().all(|r| matches!(r, Err(EvaluatorError::Failed { .. }))));
+        for result in &results[MAX_CONSECUTIVE_FAILURES..] {
+            assert_eq!(result.as_ref().unwrap_err(), &EvaluatorError::Disabled(MAX_CONSECUTIVE_FAILURES));
+        }
+        assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), MAX_CONSECUTIVE_FAILURES);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&log).unwrap();
+    }
+
+    /// A JSON-lines calculator that logs each start to `log`, skips the
+    /// instance line and answers every route with `answer`.
+    fn session_script(name: &str, log: &Path, answer: &str) -> PathBuf {
+        script(name, &format!(
+            "echo start >> '{}'\nread instance\nwhile read route; do printf '%s\\n' '{}'; done\n",
+            log.display(),
+            answer,
+        ))
+    }
+
+    fn starts(log: &Path) -> usize {
+        fs::read_to_string(log).map(|log| log.lines().count()).unwrap_or(0)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn session_serves_later_batches_without_a_restart() {
+        let log = std::env::temp_dir().join(format!("vrp_pricing_session_{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let (path, departure) = route();
+        let trip = test_trip_model().evaluate(&path, departure).unwrap();
+        let script = session_script("session", &log, &serde_json::to_string(&trip).unwrap());
+        let evaluator = calculator(&script, CalculatorProtocol::JsonLines, Some(Duration::from_secs(10)));
+
+        let first = evaluator.evaluate_batch(&[route(), route()]);
+        let second = evaluator.evaluate_batch(&[route()]);
+
+        assert_eq!(starts(&log), 1);
+        for result in first.iter().chain(&second) {
+            assert_eq!(result.as_ref().unwrap().total_cost, trip.total_cost);
+        }
+        drop(evaluator);
+        fs::remove_file(&script).unwrap();
+        fs::remove_file(&log).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn session_reports_per_route_errors() {
+        let log = std::env::temp_dir().join(format!("vrp_pricing_session_errors_{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let script = session_script("session_errors", &log, r#"{"error": "no road"}"#);
+        let evaluator = calculator(&script, CalculatorProtocol::JsonLines, Some(Duration::from_secs(10)));
+
+        let results = evaluator.evaluate_batch(&[route(), route()]);
+
+        for result in &results {
+            assert_eq!(result.as_ref().unwrap_err(), &EvaluatorError::Reported("no road".to_string()));
+        }
+        assert_eq!(starts(&log), 1, "a reported error keeps the session");
+        drop(evaluator);
+        fs::remove_file(&script).unwrap();
+        fs::remove_file(&log).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn closed_session_is_restarted_for_the_next_batch() {
+        let log = std::env::temp_dir().join(format!("vrp_pricing_session_closed_{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let script = script("session_closed", &format!("echo start >> '{}'\nread instance\n", log.display()));
+        let evaluator = calculator(&script, CalculatorProtocol::JsonLines, Some(Duration::from_secs(10)));
+
+        let first = evaluator.evaluate_batch(&[route()]);
+        let second = evaluator.evaluate_batch(&[route()]);
+
+        assert_eq!(first[0].as_ref().unwrap_err(), &EvaluatorError::SessionClosed);
+        assert_eq!(second[0].as_ref().unwrap_err(), &EvaluatorError::SessionClosed);
+        assert_eq!(starts(&log), 2);
+        fs::remove_file(&script).unwrap();
+        fs::remove_file(&log).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use geographiclib_rs::{Geodesic, InverseGeodesic};
+use crate::models::{ArcClass, DetourFactors, DistanceMetric};
+use crate::pricing::haversine_distance;
+
+/// WGS84 semi-major axis in metres and flattening.
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+const VINCENTY_MAX_ITERATIONS: usize = 200;
+
+/// Road distance estimate for arcs the matrix does not cover: the
+/// straight-line distance under `metric` times the detour factor.
+#[derive(Debug, Clone, Default)]
+pub struct DistanceModel {
+    metric: DistanceMetric,
+    detour: DetourFactors,
+}
+
+impl DistanceModel {
+    pub fn new(metric: DistanceMetric, detour: DetourFactors) -> Self {
+        Self { metric, detour }
+    }
+
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    /// Estimated road kilometres from `from` to `to`, both (lat, lng).
+    pub fn road_km(&self, from: (f64, f64), to: (f64, f64), class: ArcClass) -> f64 {
+        self.detour_factor(from, class) * self.straight_km(from, to)
+    }
+
+    pub fn straight_km(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        match self.metric {
+            DistanceMetric::Haversine => haversine_distance(from, to),
+            DistanceMetric::Vincenty => vincenty_km(from, to).unwrap_or_else(|| karney_km(from, to)),
+            DistanceMetric::Karney => karney_km(from, to),
+        }
+    }
+
+    fn detour_factor(&self, (lat, lng): (f64, f64), class: ArcClass) -> f64 {
+        let regional = self.detour.regions.iter()
+            .find(|r| (r.min_lat..=r.max_lat).contains(&lat) && (r.min_lng..=r.max_lng).contains(&lng))
+            .map_or(self.detour.factor, |r| r.factor);
+        regional * self.detour.arc_classes.get(&class).copied().unwrap_or(1.0)
+    }
+}
+
+fn karney_km((lat1, lng1): (f64, f64), (lat2, lng2): (f64, f64)) -> f64 {
+    let metres: f64 = Geodesic::wgs84().inverse(lat1, lng1, lat2, lng2);
+    metres / 1000.0
+}
+
+/// Vincenty's inverse formula; `None` if it does not converge.
+fn vincenty_km((lat1, lng1): (f64, f64), (lat2, lng2): (f64, f64)) -> Option<f64> {
+    let b = (1.0 - WGS84_F) * WGS84_A;
+    let l = (lng2 - lng1).to_radians();
+    let u1 = ((1.0 - WGS84_F) * lat1.to_radians().tan()).atan();
+    let u2 = ((1.0 - WGS84_F) * lat2.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2)).sqrt();
+        if sin_sigma == 0.0 {
+            return Some(0.0);
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+        // Both points on the equator
+        let cos_2sigma_m = if cos_sq_alpha == 0.0 { 0.0 } else { cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha };
+        let c = WGS84_F / 16.0 * cos_sq_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos_sq_alpha));
+        let previous = lambda;
+        lambda = l + (1.0 - c) * WGS84_F * sin_alpha
+            * (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+        if (lambda - previous).abs() < 1e-12 {
+            let u_sq = cos_sq_alpha * (WGS84_A * WGS84_A - b * b) / (b * b);
+            let big_a = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b * sin_sigma * (cos_2sigma_m + big_b / 4.0
+                * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                    - big_b / 6.0 * cos_2sigma_m * (-3.0 + 4.0 * sin_sigma.powi(2)) * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+            return Some(b * big_a * (sigma - delta_sigma) / 1000.0);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DetourRegion;
+
+    fn dms(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+        degrees.signum() * (degrees.abs() + minutes / 60.0 + seconds / 3600.0)
+    }
+
+    /// Vincenty's own worked example, Flinders Peak to Buninyong: 54 972.271 m.
+    #[test]
+    fn ellipsoidal_metrics_agree_on_the_reference_geodesic() {
+        let flinders_peak = (dms(-37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440));
+        let buninyong = (dms(-37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390));
+
+        let vincenty = vincenty_km(flinders_peak, buninyong).unwrap();
+        let karney = karney_km(flinders_peak, buninyong);
+
+        assert!((vincenty - 54.972271).abs() < 1e-6, "{}", vincenty);
+        assert!((karney - 54.972271).abs() < 1e-6, "{}", karney);
+        assert!((haversine_distance(flinders_peak, buninyong) - 54.972271).abs() > 0.01);
+    }
+
+    #[test]
+    fn vincenty_falls_back_to_karney_for_nearly_antipodal_points() {
+        let (from, to) = ((0.0, 0.0), (0.5, 179.7));
+        assert!(vincenty_km(from, to).is_none());
+
+        let model = DistanceModel::new(DistanceMetric::Vincenty, DetourFactors::default());
+
+        assert_eq!(model.straight_km(from, to), karney_km(from, to));
+    }
+
+    #[test]
+    fn detour_multiplies_the_region_or_default_factor_by_the_class_factor() {
+        let detour = DetourFactors {
+            factor: 1.2,
+            regions: vec![DetourRegion {
+                id: "centre".to_string(),
+                min_lat: 43.0,
+                max_lat: 44.0,
+                min_lng: 76.0,
+                max_lng: 77.0,
+                factor: 1.5,
+            }],
+            arc_classes: [(ArcClass::ToWarehouse, 2.0)].into_iter().collect(),
+        };
+        let model = DistanceModel::new(DistanceMetric::Haversine, detour);
+        let (inside, outside) = ((43.2, 76.9), (42.0, 75.0));
+        let straight = |from| haversine_distance(from, (43.3, 76.95));
+
+        let km = |from, class| model.road_km(from, (43.3, 76.95), class);
+
+        assert!((km(inside, ArcClass::BetweenCustomers) - 1.5 * straight(inside)).abs() < 1e-9);
+        assert!((km(outside, ArcClass::BetweenCustomers) - 1.2 * straight(outside)).abs() < 1e-9);
+        assert!((km(inside, ArcClass::ToWarehouse) - 3.0 * straight(inside)).abs() < 1e-9);
+    }
+}
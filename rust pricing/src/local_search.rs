@@ -0,0 +1,397 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::models::{EdgeData, PenaltyParams};
+use crate::pricing::PricingProblem;
+use crate::travel_time::minutes;
+
+const IMPROVEMENT_EPS: f64 = 1e-9;
+const MAX_OR_OPT_SEGMENT: usize = 3;
+
+/// Intra-route neighbourhood moves. Positions index the current route,
+/// where 0 is the start warehouse and `len - 1` the end warehouse.
+#[derive(Debug, Clone, Copy)]
+enum Move {
+    /// Reverse the customers at positions `i..=j`.
+    TwoOpt { i: usize, j: usize },
+    /// Move the `len` customers starting at `from` to just after position `after`.
+    /// Relocate is the single-customer case, or-opt moves chains of 2-3.
+    Shift { from: usize, len: usize, after: usize },
+    /// Swap the customers at positions `i` and `j`.
+    Exchange { i: usize, j: usize },
+}
+
+/// Timing of a single stop given the time the vehicle left the previous one.
+pub(crate) struct Visit {
+    pub(crate) departure: DateTime<Utc>,
+    pub(crate) penalty: f64,
+    pub(crate) feasible: bool,
+}
+
+/// Forward and backward data of the current route, rebuilt after every
+/// accepted move so candidate moves can be checked without re-simulating
+/// the whole route.
+struct RouteState {
+    /// Time the vehicle leaves each position (arrival for the end warehouse).
+    departure: Vec<DateTime<Utc>>,
+    /// Latest service start at each position that keeps the remainder of
+    /// the route within its windows; `None` means unbounded.
+    latest_start: Vec<Option<DateTime<Utc>>>,
+    /// Penalty accumulated up to and including each position.
+    penalty: Vec<f64>,
+    /// Arc cost accumulated up to each position, along the route.
+    forward_cost: Vec<f64>,
+    /// Arc cost accumulated up to each position, against the route.
+    backward_cost: Vec<f64>,
+}
+
+/// Arc costs, travel times and windows between the nodes of one route,
+/// indexed by the node's position in the path it was built from.
+pub(crate) struct RouteData<'a> {
+    pub(crate) cost: Vec<Vec<f64>>,
+    /// Travel times at constant speed, for the backward pass.
+    travel: Vec<Vec<TimeDelta>>,
+    /// Arcs for departure-dependent travel times.
+    arcs: Vec<Vec<Option<&'a EdgeData>>>,
+    problem: &'a PricingProblem,
+    windows: Vec<Option<(DateTime<Utc>, DateTime<Utc>)>>,
+    service: TimeDelta,
+    penalties: &'a PenaltyParams,
+    pub(crate) departure: DateTime<Utc>,
+    pub(crate) allow_violation: bool,
+}
+
+impl PricingProblem {
+    /// Improves the customer order of a closed route with 2-opt, relocate,
+    /// or-opt and exchange moves.
+    ///
+    /// Without `allow_violate_time_window` only moves that keep every stop
+    /// inside its window are accepted and the route distance is minimized.
+    /// Otherwise waiting and lateness penalties are added to the distance
+    /// cost and traded against it.
+    pub(crate) fn improve_route(&self, path: &[String], departure: DateTime<Utc>) -> Vec<String> {
+        if path.len() <= 3 {
+            return path.to_vec();
+        }
+
+        let data = self.route_data(path, departure);
+        let mut route: Vec<usize> = (0..path.len()).collect();
+
+        loop {
+            let state = data.state(&route);
+            match data.first_improving_move(&route, &state) {
+                Some(mv) => route = apply_move(&route, mv).0,
+                None => break,
+            }
+        }
+
+        route.into_iter().map(|pos| path[pos].clone()).collect()
+    }
+
+    pub(crate) fn route_data(&self, path: &[String], departure: DateTime<Utc>) -> RouteData<'_> {
+        let n = path.len();
+        let mut cost = vec![vec![f64::INFINITY; n]; n];
+        let mut travel = vec![vec![TimeDelta::zero(); n]; n];
+        let mut arcs = vec![vec![None; n]; n];
+
+        for (a, u) in path.iter().enumerate() {
+            for (b, v) in path.iter().enumerate() {
+                if let Some(edge) = self.edge(u, v) {
+                    cost[a][b] = edge.cost;
+                    travel[a][b] = edge.travel_time;
+                    arcs[a][b] = Some(edge);
+                }
+            }
+        }
+
+        let windows = path.iter()
+            .map(|node| self.customer(node).map(|c| (c.window_start, c.window_end)))
+            .collect();
+
+        RouteData {
+            cost,
+            travel,
+            arcs,
+            problem: self,
+            windows,
+            service: self.service_duration(),
+            penalties: self.penalties(),
+            departure,
+            allow_violation: self.allow_violate_time_window(),
+        }
+    }
+}
+
+impl RouteData<'_> {
+    pub(crate) fn visit(&self, from: usize, to: usize, ready: DateTime<Utc>) -> Visit {
+        let arrival = self.arcs[from][to].map_or(ready, |edge| self.problem.arrival(edge, ready));
+        match self.windows[to] {
+            None => Visit { departure: arrival, penalty: 0.0, feasible: true },
+            Some((window_start, window_end)) => {
+                let start = arrival.max(window_start);
+                let departure = start + self.service;
+                let penalty = self.penalties.waiting_per_minute * minutes(window_start - arrival)
+                    + self.penalties.late_arrival_per_minute * minutes(arrival - window_end)
+                    + self.penalties.late_service_per_minute * minutes(departure - window_end);
+                Visit { departure, penalty, feasible: departure <= window_end }
+            }
+        }
+    }
+
+    /// Objective of a complete route: arc cost, plus penalties when windows
+    /// may be violated. `None` if it breaks a window that must be kept.
+    pub(crate) fn objective(&self, route: &[usize]) -> Option<f64> {
+        let mut ready = self.departure;
+        let mut total = 0.0;
+        for pair in route.windows(2) {
+            let visit = self.visit(pair[0], pair[1], ready);
+            if !self.allow_violation && !visit.feasible {
+                return None;
+            }
+            ready = visit.departure;
+            total += self.cost[pair[0]][pair[1]] + self.visit_penalty(&visit);
+        }
+        Some(total)
+    }
+
+    /// Penalty of a visit as it counts towards the objective.
+    pub(crate) fn visit_penalty(&self, visit: &Visit) -> f64 {
+        if self.allow_violation { visit.penalty } else { 0.0 }
+    }
+
+    /// Objective cost of leaving one minute earlier than necessary: time
+    /// gained can at worst be spent waiting further down the route.
+    pub(crate) fn waiting_rate(&self) -> f64 {
+        if self.allow_violation { self.penalties.waiting_per_minute } else { 0.0 }
+    }
+
+    fn state(&self, route: &[usize]) -> RouteState {
+        let n = route.len();
+        let mut departure = Vec::with_capacity(n);
+        let mut penalty = Vec::with_capacity(n);
+        let mut forward_cost = Vec::with_capacity(n);
+        let mut backward_cost = Vec::with_capacity(n);
+
+        departure.push(self.departure);
+        penalty.push(0.0);
+        forward_cost.push(0.0);
+        backward_cost.push(0.0);
+
+        for k in 1..n {
+            let (u, v) = (route[k - 1], route[k]);
+            let visit = self.visit(u, v, departure[k - 1]);
+            departure.push(visit.departure);
+            penalty.push(penalty[k - 1] + visit.penalty);
+            forward_cost.push(forward_cost[k - 1] + self.cost[u][v]);
+            backward_cost.push(backward_cost[k - 1] + self.cost[v][u]);
+        }
+
+        // Backward pass: a stop may start no later than its own window allows
+        // and early enough to reach the next stop before that one's latest start.
+        let mut latest_start: Vec<Option<DateTime<Utc>>> = vec![None; n];
+        for k in (1..n - 1).rev() {
+            let node = route[k];
+            let Some((_, window_end)) = self.windows[node] else {
+                continue;
+            };
+            let own = window_end - self.service;
+
+            let next = route[k + 1];
+            let latest = match (latest_start[k + 1], self.windows[next]) {
+                (None, _) => own,
+                (Some(next_latest), Some((next_start, _))) if next_latest < next_start => {
+                    DateTime::<Utc>::MIN_UTC
+                }
+                (Some(next_latest), _) => next_latest
+                    .checked_sub_signed(self.travel[node][next] + self.service)
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC)
+                    .min(own),
+            };
+            latest_start[k] = Some(latest);
+        }
+
+        RouteState { departure, latest_start, penalty, forward_cost, backward_cost }
+    }
+
+    fn first_improving_move(&self, route: &[usize], state: &RouteState) -> Option<Move> {
+        neighbourhood(route.len()).find(|&mv| self.improves(route, state, mv))
+    }
+
+    fn improves(&self, route: &[usize], state: &RouteState, mv: Move) -> bool {
+        let distance_delta = self.distance_delta(route, state, mv);
+        if !self.allow_violation && distance_delta > -IMPROVEMENT_EPS {
+            return false;
+        }
+
+        let (candidate, first, last) = apply_move(route, mv);
+        match self.penalty_delta(&candidate, state, first, last) {
+            Some(penalty_delta) => distance_delta + penalty_delta < -IMPROVEMENT_EPS,
+            None => false,
+        }
+    }
+
+    /// Change in arc cost of a move, computed from the arcs it removes and adds.
+    fn distance_delta(&self, r: &[usize], state: &RouteState, mv: Move) -> f64 {
+        let c = |a: usize, b: usize| self.cost[r[a]][r[b]];
+        match mv {
+            Move::TwoOpt { i, j } => {
+                let forward_inner = state.forward_cost[j] - state.forward_cost[i];
+                let backward_inner = state.backward_cost[j] - state.backward_cost[i];
+                c(i - 1, j) + backward_inner + c(i, j + 1)
+                    - c(i - 1, i) - forward_inner - c(j, j + 1)
+            }
+            Move::Shift { from, len, after } => {
+                let end = from + len - 1;
+                c(from - 1, end + 1) + c(after, from) + c(end, after + 1)
+                    - c(from - 1, from) - c(end, end + 1) - c(after, after + 1)
+            }
+            Move::Exchange { i, j } if j == i + 1 => {
+                c(i - 1, j) + c(j, i) + c(i, j + 1)
+                    - c(i - 1, i) - c(i, j) - c(j, j + 1)
+            }
+            Move::Exchange { i, j } => {
+                c(i - 1, j) + c(j, i + 1) + c(j - 1, i) + c(i, j + 1)
+                    - c(i - 1, i) - c(i, i + 1) - c(j - 1, j) - c(j, j + 1)
+            }
+        }
+    }
+
+    /// Re-times the changed positions `first..=last` of a candidate route.
+    ///
+    /// Returns `None` if the candidate breaks a window that must be kept,
+    /// otherwise the change in penalty (always zero when windows are strict).
+    fn penalty_delta(&self, candidate: &[usize], state: &RouteState, first: usize, last: usize) -> Option<f64> {
+        let mut ready = state.departure[first - 1];
+        let mut penalty = state.penalty[first - 1];
+
+        for k in first..=last {
+            let visit = self.visit(candidate[k - 1], candidate[k], ready);
+            if !self.allow_violation && !visit.feasible {
+                return None;
+            }
+            ready = visit.departure;
+            penalty += visit.penalty;
+        }
+
+        if !self.allow_violation && !self.problem.travel().is_time_dependent() {
+            // The rest of the route is unchanged, so the forward slack tells us
+            // whether it still fits without walking it again.
+            let next = last + 1;
+            let arrival = ready + self.travel[candidate[last]][candidate[next]];
+            let start = self.windows[candidate[next]]
+                .map_or(arrival, |(window_start, _)| arrival.max(window_start));
+            let fits = state.latest_start[next].is_none_or(|latest| start <= latest);
+            return fits.then_some(0.0);
+        }
+
+        let end = candidate.len() - 1;
+        for k in last + 1..=end {
+            let visit = self.visit(candidate[k - 1], candidate[k], ready);
+            if !self.allow_violation && !visit.feasible {
+                return None;
+            }
+            ready = visit.departure;
+            penalty += visit.penalty;
+            if ready == state.departure[k] {
+                // Back on the old schedule: the remaining penalty is unchanged.
+                penalty += state.penalty[end] - state.penalty[k];
+                break;
+            }
+        }
+
+        Some(if self.allow_violation { penalty - state.penalty[end] } else { 0.0 })
+    }
+}
+
+/// Every move on a route of `len` positions, in the order they are tried.
+fn neighbourhood(len: usize) -> impl Iterator<Item = Move> {
+    let last = len - 2;
+
+    let two_opt = (1..last)
+        .flat_map(move |i| (i + 1..=last).map(move |j| Move::TwoOpt { i, j }));
+    let shifts = (1..=MAX_OR_OPT_SEGMENT).flat_map(move |len| {
+        (1..=(last + 1).saturating_sub(len)).flat_map(move |from| {
+            (0..=last)
+                .filter(move |&after| after + 1 < from || after >= from + len)
+                .map(move |after| Move::Shift { from, len, after })
+        })
+    });
+    let exchanges = (1..last)
+        .flat_map(move |i| (i + 1..=last).map(move |j| Move::Exchange { i, j }));
+
+    two_opt.chain(shifts).chain(exchanges)
+}
+
+/// Applies a move and returns the new route with the first and last
+/// position whose node changed.
+fn apply_move(route: &[usize], mv: Move) -> (Vec<usize>, usize, usize) {
+    let mut next = route.to_vec();
+    match mv {
+        Move::TwoOpt { i, j } => {
+            next[i..=j].reverse();
+            (next, i, j)
+        }
+        Move::Shift { from, len, after } => {
+            let segment: Vec<usize> = next.drain(from..from + len).collect();
+            let insert_at = if after < from { after + 1 } else { after + 1 - len };
+            next.splice(insert_at..insert_at, segment);
+            if after < from {
+                (next, after + 1, from + len - 1)
+            } else {
+                (next, from, after)
+            }
+        }
+        Move::Exchange { i, j } => {
+            next.swap(i, j);
+            (next, i, j)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_pricing, test_input};
+
+    #[test]
+    fn apply_move_rearranges_and_reports_changed_positions() {
+        let route: Vec<usize> = (0..7).collect();
+        assert_eq!(apply_move(&route, Move::TwoOpt { i: 2, j: 4 }), (vec![0, 1, 4, 3, 2, 5, 6], 2, 4));
+        assert_eq!(apply_move(&route, Move::Shift { from: 1, len: 2, after: 4 }), (vec![0, 3, 4, 1, 2, 5, 6], 1, 4));
+        assert_eq!(apply_move(&route, Move::Shift { from: 4, len: 1, after: 1 }), (vec![0, 1, 4, 2, 3, 5, 6], 2, 4));
+        assert_eq!(apply_move(&route, Move::Exchange { i: 1, j: 5 }), (vec![0, 5, 2, 3, 4, 1, 6], 1, 5));
+    }
+
+    #[test]
+    fn moves_keep_the_warehouses_and_positions_outside_their_range() {
+        let route: Vec<usize> = (0..9).collect();
+        for mv in neighbourhood(route.len()) {
+            let (next, first, last) = apply_move(&route, mv);
+            let mut sorted = next.clone();
+            sorted.sort();
+            assert_eq!(sorted, route, "{:?}", mv);
+            assert!(first >= 1 && last <= route.len() - 2, "{:?}", mv);
+            for k in (0..first).chain(last + 1..route.len()) {
+                assert_eq!(next[k], route[k], "{:?} changed position {}", mv, k);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_delta_matches_recomputed_route_cost() {
+        let pricing = build_pricing(test_input()).unwrap();
+        let path: Vec<String> = std::iter::once("W_1".to_string())
+            .chain((1..=8).map(|id| format!("C_{}", id)))
+            .chain(std::iter::once("W_1".to_string()))
+            .collect();
+        let data = pricing.route_data(&path, pricing.departure_from("W_1"));
+        let route: Vec<usize> = (0..path.len()).collect();
+        let state = data.state(&route);
+        let cost = |route: &[usize]| route.windows(2).map(|pair| data.cost[pair[0]][pair[1]]).sum::<f64>();
+
+        for mv in neighbourhood(route.len()) {
+            let (candidate, _, _) = apply_move(&route, mv);
+            let expected = cost(&candidate) - cost(&route);
+            let delta = data.distance_delta(&route, &state, mv);
+            assert!((delta - expected).abs() < 1e-9, "{:?}: {} vs {}", mv, delta, expected);
+        }
+    }
+}
@@ -1,19 +1,51 @@
+mod consistency;
+mod departure;
+mod diagnostics;
+mod error;
+mod evaluation;
+mod evaluator;
+mod geodesy;
+mod local_search;
+mod matrix;
 mod models;
+mod plan_report;
 mod pricing;
+mod road_graph;
+mod route_cache;
+mod sequencing;
+mod timezone;
+mod travel_time;
+mod trip_cost;
+mod validation;
 
 use std::io;
-use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use chrono::TimeDelta;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde_json::{from_str, to_string};
-use crate::models::{InputData, PathResult};
+use crate::models::{default_calculator_args, default_calculator_timeout};
+use crate::models::{CalculatorProtocol, EvaluatorConfig, EvaluatorFallback, InputData, TravelMatrix, ValidationReport};
+use crate::error::PricingError;
+use crate::geodesy::DistanceModel;
+use crate::plan_report::write_route_csv;
 use crate::pricing::PricingProblem;
 
 #[derive(Parser)]
 #[command(name = "VRP Pricing Solver")]
 #[command(version = "1.0")]
 #[command(about = "Solves VRP pricing problem as standalone executable")]
+#[command(after_help = "Exit codes: 0 success, 2 usage, 3 parse, 4 validation, 5 evaluator, \
+6 limit, 7 consistency, 8 I/O")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// On failure, also print a JSON error object on stdout, or on stderr
+    /// if the command already wrote its output there
+    #[arg(long, global = true)]
+    json_errors: bool,
 }
 
 #[derive(Subcommand)]
@@ -26,57 +58,425 @@ enum Commands {
         /// Output JSON file or '-' for stdout
         #[arg(short, long)]
         output: Option<String>,
+
+        #[command(flatten)]
+        evaluator: EvaluatorArgs,
+
+        /// Compare edge and evaluator cost of the returned route, failing
+        /// if they differ by more than this
+        #[arg(long)]
+        consistency_tolerance: Option<f64>,
+
+        /// Give up on the labeling after this many seconds
+        #[arg(long)]
+        time_limit: Option<f64>,
+    },
+    /// Check given routes against JSON input
+    Evaluate {
+        /// Input JSON file or '-' for stdin
+        input: String,
+
+        #[command(flatten)]
+        routes: RouteArgs,
+
+        /// Output JSON file or '-' for stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(flatten)]
+        evaluator: EvaluatorArgs,
+    },
+    /// Report KPIs of a whole plan of routes
+    PlanReport {
+        /// Input JSON file or '-' for stdin
+        input: String,
+
+        #[command(flatten)]
+        routes: RouteArgs,
+
+        /// Output JSON file or '-' for stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Also write the per-route table as CSV to this file, or '-' for
+        /// stdout when --output names a file
+        #[arg(long)]
+        csv: Option<String>,
+
+        #[command(flatten)]
+        evaluator: EvaluatorArgs,
+    },
+    /// Check JSON input and report every problem found
+    Validate {
+        /// Input JSON file or '-' for stdin
+        input: String,
+
+        /// Output JSON file or '-' for stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List customers no route can visit, with the reasons
+    Diagnose {
+        /// Input JSON file or '-' for stdin
+        input: String,
+
+        /// Output JSON file or '-' for stdout
+        #[arg(short, long)]
+        output: Option<String>,
     },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Routes given on the command line
+#[derive(Args)]
+struct RouteArgs {
+    /// Route as comma-separated node ids, e.g. W_1,C_4,C_9,W_1; repeatable
+    #[arg(long, required_unless_present = "paths_file")]
+    path: Vec<String>,
+
+    /// JSON file with an array of routes, each an array of node ids
+    #[arg(long)]
+    paths_file: Option<String>,
+}
+
+impl RouteArgs {
+    fn paths(self) -> Result<Vec<Vec<String>>, PricingError> {
+        let mut paths: Vec<Vec<String>> = self.path.iter()
+            .map(|nodes| nodes.split(',').map(|node| node.trim().to_string()).collect())
+            .collect();
+        if let Some(file) = self.paths_file {
+            paths.extend(from_str::<Vec<Vec<String>>>(&std::fs::read_to_string(file)?)?);
+        }
+        Ok(paths)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EvaluatorKind {
+    Internal,
+    Subprocess,
+    Mock,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ProtocolKind {
+    PerRoute,
+    JsonLines,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FallbackKind {
+    Skip,
+    EdgeCost,
+}
+
+/// Overrides for the input's `evaluator` section
+#[derive(Args)]
+struct EvaluatorArgs {
+    /// Trip cost evaluator for finished routes
+    #[arg(long, value_enum)]
+    evaluator: Option<EvaluatorKind>,
+
+    /// External trip calculator executable (implies --evaluator subprocess)
+    #[arg(long)]
+    calculator_path: Option<String>,
+
+    /// Calculator argument, repeatable; the input is written to its stdin
+    #[arg(long, allow_hyphen_values = true)]
+    calculator_arg: Vec<String>,
+
+    /// Per-call calculator timeout in seconds
+    #[arg(long)]
+    calculator_timeout: Option<f64>,
+
+    /// One calculator process per route, or one JSON-lines session per run
+    #[arg(long, value_enum)]
+    calculator_protocol: Option<ProtocolKind>,
+
+    /// What to do with a route the evaluator fails to cost
+    #[arg(long, value_enum)]
+    evaluator_fallback: Option<FallbackKind>,
+
+    /// Memoize trip costs within the run
+    #[arg(long)]
+    route_cache: bool,
+
+    /// Memoize trip costs in this file, across runs
+    #[arg(long)]
+    route_cache_file: Option<String>,
+}
+
+impl EvaluatorArgs {
+    fn apply(self, input: &mut InputData) -> Result<(), PricingError> {
+        match self.evaluator_fallback {
+            Some(FallbackKind::Skip) => input.evaluator_fallback = EvaluatorFallback::Skip,
+            Some(FallbackKind::EdgeCost) => input.evaluator_fallback = EvaluatorFallback::EdgeCost,
+            None => {}
+        }
+
+        if self.route_cache {
+            input.route_cache.enabled = true;
+        }
+        if self.route_cache_file.is_some() {
+            input.route_cache.file = self.route_cache_file;
+        }
+
+        let config = &mut input.evaluator;
+
+        let kind = self.evaluator
+            .or(self.calculator_path.as_ref().map(|_| EvaluatorKind::Subprocess));
+
+        match kind {
+            Some(EvaluatorKind::Internal) => *config = EvaluatorConfig::Internal,
+            Some(EvaluatorKind::Mock) if !matches!(config, EvaluatorConfig::Mock { .. }) => {
+                *config = EvaluatorConfig::Mock { fixed_cost: 0.0, cost_per_stop: 0.0 };
+            }
+            Some(EvaluatorKind::Subprocess) if !matches!(config, EvaluatorConfig::Subprocess { .. }) => {
+                *config = EvaluatorConfig::Subprocess {
+                    path: self.calculator_path.clone().ok_or_else(|| {
+                        PricingError::Usage("--evaluator subprocess needs --calculator-path".to_string())
+                    })?,
+                    args: default_calculator_args(),
+                    timeout_secs: default_calculator_timeout(),
+                    protocol: CalculatorProtocol::default(),
+                };
+            }
+            _ => {}
+        }
+
+        if let EvaluatorConfig::Subprocess { path, args, timeout_secs, protocol } = config {
+            if let Some(calculator_path) = self.calculator_path {
+                *path = calculator_path;
+            }
+            if !self.calculator_arg.is_empty() {
+                *args = self.calculator_arg;
+            }
+            if self.calculator_timeout.is_some() {
+                *timeout_secs = self.calculator_timeout;
+            }
+            match self.calculator_protocol {
+                Some(ProtocolKind::PerRoute) => *protocol = CalculatorProtocol::PerRoute,
+                Some(ProtocolKind::JsonLines) => *protocol = CalculatorProtocol::JsonLines,
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    match cli.command {
-        Commands::Solve { input, output } => {
-            // Read input
-            let input_str = if input == "-" {
-                let mut buffer = String::new();
-                io::stdin().read_line(&mut buffer)?;
-                buffer
-            } else {
-                std::fs::read_to_string(input)?
-            };
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            if let PricingError::Validation(report) = &e {
+                for issue in &report.issues {
+                    eprintln!("  {}: {}", issue.field, issue.message);
+                }
+            }
+            if cli.json_errors {
+                // Keep stdout a single JSON document
+                if STDOUT_USED.load(Ordering::Relaxed) {
+                    eprintln!("{}", e.to_json());
+                } else {
+                    println!("{}", e.to_json());
+                }
+            }
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-            let input_data: InputData = from_str(&input_str)?;
+fn run(command: Commands) -> Result<(), PricingError> {
+    match command {
+        Commands::Solve { input, output, evaluator, consistency_tolerance, time_limit } => {
+            let input_data = read_input(&input, |input_data| {
+                evaluator.apply(input_data)?;
+                if consistency_tolerance.is_some() {
+                    input_data.consistency_tolerance = consistency_tolerance;
+                }
+                if time_limit.is_some() {
+                    input_data.time_limit_secs = time_limit;
+                }
+                Ok(())
+            })?;
+            let consistency_tolerance = input_data.consistency_tolerance;
+            // Validated as finite and non-negative; a limit too long for a Duration is no limit
+            let time_limit = input_data.time_limit_secs
+                .map(|secs| Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX));
 
             // Solve problem
-            let pricing = PricingProblem::new(
-                input_data.customers,
-                input_data.warehouses,
-                &input_data.dual_values,
-                input_data.max_stops,
-                input_data.max_capacity,
-                input_data.cost_per_km,
-                input_data.speed_kmh,
-                input_data.service_time,
-                input_data.planning_date, 
-                input_data.departure_hour, 
-                input_data.allow_violate_time_window, 
-                input_data.penalties,
-            );
-
-            let result = pricing.find_negative_path();
-
-            // Write output
-            let output_str = to_string(&result)?;
-            
-            if let Some(output_path) = output {
-                if output_path == "-" {
-                    println!("{}", output_str);
-                } else {
-                    std::fs::write(output_path, output_str)?;
+            let pricing = build_pricing(input_data)?;
+            let result = pricing.find_negative_path(time_limit)?;
+
+            write_output(output, &to_string(&result)?)?;
+
+            // The report is written either way; a failed check only sets the exit status
+            if let (Some(_), Some(result)) = (consistency_tolerance, result) {
+                match result.consistency {
+                    Some(report) if !report.within_tolerance => {
+                        return Err(PricingError::Consistency(Box::new(report)));
+                    }
+                    Some(_) => {}
+                    None => return Err(PricingError::Evaluator(
+                        "the consistency check needs an evaluator cost for the returned route".to_string()
+                    )),
                 }
-            } else {
-                println!("{}", output_str);
+            }
+        }
+        Commands::Evaluate { input, routes, output, evaluator } => {
+            let input_data = read_input(&input, |input_data| evaluator.apply(input_data))?;
+            let paths = routes.paths()?;
+
+            let pricing = build_pricing(input_data)?;
+            let reports = pricing.evaluate_paths(&paths)?;
+
+            write_output(output, &to_string(&reports)?)?;
+        }
+        Commands::PlanReport { input, routes, output, csv, evaluator } => {
+            if csv.as_deref() == Some("-") && output.as_deref().is_none_or(|output| output == "-") {
+                return Err(PricingError::Usage(
+                    "--csv - needs --output to name a file, stdout already holds the JSON report".to_string()
+                ));
+            }
+            let input_data = read_input(&input, |input_data| evaluator.apply(input_data))?;
+            let paths = routes.paths()?;
+
+            let pricing = build_pricing(input_data)?;
+            let report = pricing.plan_report(&paths)?;
+
+            write_output(output, &to_string(&report)?)?;
+            match csv.as_deref() {
+                Some("-") => {
+                    STDOUT_USED.store(true, Ordering::Relaxed);
+                    write_route_csv(io::stdout(), &report.routes)?;
+                }
+                Some(csv_path) => write_route_csv(std::fs::File::create(csv_path)?, &report.routes)?,
+                None => {}
+            }
+        }
+        Commands::Diagnose { input, output } => {
+            let pricing = build_pricing(read_input(&input, |_| Ok(()))?)?;
+            write_output(output, &to_string(&pricing.infeasibility_report())?)?;
+        }
+        Commands::Validate { input, output } => {
+            let report = parse_input(&input)?.validate();
+            write_output(output, &to_string(&report)?)?;
+            if !report.valid {
+                return Err(PricingError::Validation(report));
             }
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Reads `InputData` from a file, or a single line of stdin for '-'.
+fn parse_input(input: &str) -> Result<InputData, PricingError> {
+    let input_str = if input == "-" {
+        let mut buffer = String::new();
+        io::stdin().read_line(&mut buffer)?;
+        buffer
+    } else {
+        std::fs::read_to_string(input)?
+    };
+
+    Ok(from_str(&input_str)?)
+}
+
+/// Like `parse_input` with the command-line `overrides` applied, but
+/// refuses input that then fails validation.
+fn read_input(
+    input: &str,
+    overrides: impl FnOnce(&mut InputData) -> Result<(), PricingError>,
+) -> Result<InputData, PricingError> {
+    let mut input_data = parse_input(input)?;
+    overrides(&mut input_data)?;
+    let report = input_data.validate();
+    if !report.valid {
+        return Err(PricingError::Validation(report));
+    }
+    Ok(input_data)
+}
+
+fn build_pricing(input_data: InputData) -> Result<PricingProblem, PricingError> {
+    let known = match &input_data.matrix {
+        Some(input) => input.load()?,
+        None => TravelMatrix::default(),
+    };
+    let mut matrix = match &input_data.road_graph {
+        Some(road_graph) => road_graph.matrix(&input_data, &known)?,
+        None => TravelMatrix::default(),
+    };
+    matrix.overlay(&known);
+    let complete = input_data.matrix.as_ref().is_some_and(|matrix| matrix.requires_complete());
+    let issues = input_data.matrix_issues(&matrix, complete);
+    if !issues.is_empty() {
+        return Err(PricingError::Validation(ValidationReport { valid: false, issues }));
+    }
+    let vehicle_type = input_data.vehicle_type.as_ref()
+        .and_then(|id| input_data.vehicle_types.iter().find(|vt| &vt.id == id))
+        .cloned();
+    PricingProblem::new(
+        input_data.customers,
+        input_data.warehouses,
+        &input_data.dual_values,
+        input_data.max_stops,
+        input_data.max_capacity,
+        input_data.cost_per_km,
+        input_data.speed_kmh,
+        input_data.speed_profile,
+        matrix,
+        DistanceModel::new(input_data.distance_metric, input_data.detour),
+        input_data.service_time_secs.map_or(TimeDelta::minutes(input_data.service_time), TimeDelta::seconds),
+        input_data.time_resolution_secs,
+        input_data.planning_date, 
+        input_data.departure_hour, 
+        input_data.timezone.as_deref(),
+        vehicle_type.as_ref(),
+        input_data.departure_mode,
+        input_data.allow_violate_time_window, 
+        input_data.penalties,
+        input_data.exact_sequencing_max_stops,
+        input_data.fixed_vehicle_cost,
+        &input_data.evaluator,
+        input_data.evaluator_fallback,
+        &input_data.route_cache,
+        input_data.consistency_tolerance,
+    )
+}
+
+/// Set once a command's output has gone to stdout.
+static STDOUT_USED: AtomicBool = AtomicBool::new(false);
+
+/// Writes to the output file, or stdout if there is none or it is '-'.
+fn write_output(output: Option<String>, output_str: &str) -> io::Result<()> {
+    match output {
+        Some(output_path) if output_path != "-" => std::fs::write(output_path, output_str),
+        _ => {
+            STDOUT_USED.store(true, Ordering::Relaxed);
+            println!("{}", output_str);
+            Ok(())
+        }
+    }
+}
+
+/// `test_input.json`, the instance the tests share.
+#[cfg(test)]
+fn test_input() -> InputData {
+    from_str(include_str!("../test_input.json")).expect("test_input.json parses")
+}
+
+/// The in-process trip cost model of `test_input.json`.
+#[cfg(test)]
+fn test_trip_model() -> trip_cost::TripCostModel {
+    let input = test_input();
+    let locations = input.warehouses.iter()
+        .map(|wh| trip_cost::Location::from_warehouse(&format!("W_{}", wh.id), wh))
+        .chain(input.customers.iter().map(|cust| trip_cost::Location::from_customer(&format!("C_{}", cust.id), cust)))
+        .collect();
+    let travel = travel_time::TravelModel::new(input.speed_kmh, input.time_resolution_secs, timezone::PlanningZone::default(), None);
+    trip_cost::TripCostModel::new(locations, input.cost_per_km, travel, input.service_time, input.max_capacity, input.max_stops, input.penalties)
+}
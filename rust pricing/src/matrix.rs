@@ -0,0 +1,250 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use crate::error::PricingError;
+use crate::models::{MatrixFormat, MatrixInput, TravelMatrix};
+
+/// Rows of an OSRM `table` response; only the fields we read.
+#[derive(Deserialize)]
+struct OsrmTable {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    distances: Option<Vec<Vec<Option<f64>>>>,
+    #[serde(default)]
+    durations: Option<Vec<Vec<Option<f64>>>>,
+}
+
+#[derive(Deserialize)]
+struct ValhallaMatrix {
+    sources_to_targets: Vec<Vec<ValhallaCell>>,
+}
+
+#[derive(Deserialize)]
+struct ValhallaCell {
+    #[serde(default)]
+    from_index: Option<usize>,
+    #[serde(default)]
+    to_index: Option<usize>,
+    #[serde(default)]
+    distance: Option<f64>,
+    #[serde(default)]
+    time: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    from: String,
+    to: String,
+    meters: Option<f64>,
+    seconds: Option<f64>,
+}
+
+impl MatrixInput {
+    /// The complete matrix: the file's entries, if there is a file, with the
+    /// inline ones on top.
+    pub fn load(&self) -> Result<TravelMatrix, PricingError> {
+        let mut matrix = match &self.file {
+            Some(file) => {
+                let text = std::fs::read_to_string(file)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Matrix file {}: {}", file, e)))?;
+                self.read(&text).map_err(|e| match e {
+                    PricingError::Parse(e) => parse_error(format!("matrix file {}: {}", file, e)),
+                    e => e,
+                })?
+            }
+            None => TravelMatrix::default(),
+        };
+        matrix.overlay(&self.matrix);
+        Ok(matrix)
+    }
+
+    /// Whether every arc must be covered; see `require_complete`.
+    pub fn requires_complete(&self) -> bool {
+        self.require_complete.unwrap_or(self.file.is_some())
+    }
+
+    fn read(&self, text: &str) -> Result<TravelMatrix, PricingError> {
+        let destinations = if self.destinations.is_empty() { &self.sources } else { &self.destinations };
+        match self.format {
+            MatrixFormat::Json => Ok(serde_json::from_str(text)?),
+            MatrixFormat::OsrmTable => {
+                let table: OsrmTable = serde_json::from_str(text)?;
+                if let Some(code) = table.code.as_deref().filter(|code| *code != "Ok") {
+                    return Err(parse_error(format!(
+                        "OSRM table has code {}: {}", code, table.message.unwrap_or_default()
+                    )));
+                }
+                let mut matrix = TravelMatrix::default();
+                if let Some(rows) = &table.distances {
+                    matrix.distances_km = from_rows(rows, &self.sources, destinations, "distances", 0.001)?;
+                }
+                if let Some(rows) = &table.durations {
+                    matrix.durations_secs = from_rows(rows, &self.sources, destinations, "durations", 1.0)?;
+                }
+                Ok(matrix)
+            }
+            MatrixFormat::Valhalla => {
+                let table: ValhallaMatrix = serde_json::from_str(text)?;
+                let mut matrix = TravelMatrix::default();
+                for (i, row) in table.sources_to_targets.iter().enumerate() {
+                    for (j, cell) in row.iter().enumerate() {
+                        let from = node_at(&self.sources, cell.from_index.unwrap_or(i), "source")?;
+                        let to = node_at(destinations, cell.to_index.unwrap_or(j), "target")?;
+                        insert(&mut matrix.distances_km, from, to, cell.distance);
+                        insert(&mut matrix.durations_secs, from, to, cell.time);
+                    }
+                }
+                Ok(matrix)
+            }
+            MatrixFormat::Csv => {
+                let mut matrix = TravelMatrix::default();
+                for row in csv::Reader::from_reader(text.as_bytes()).deserialize::<CsvRow>() {
+                    let row = row.map_err(|e| parse_error(e.to_string()))?;
+                    insert(&mut matrix.distances_km, &row.from, &row.to, row.meters.map(|m| m / 1000.0));
+                    insert(&mut matrix.durations_secs, &row.from, &row.to, row.seconds);
+                }
+                Ok(matrix)
+            }
+        }
+    }
+}
+
+impl TravelMatrix {
+    pub fn distance_km(&self, from: &str, to: &str) -> Option<f64> {
+        self.distances_km.get(from)?.get(to).copied()
+    }
+
+    pub fn duration_secs(&self, from: &str, to: &str) -> Option<f64> {
+        self.durations_secs.get(from)?.get(to).copied()
+    }
+
+    /// Replaces this matrix's entries with those of `top`, keeping the rest.
+    pub fn overlay(&mut self, top: &TravelMatrix) {
+        overlay(&mut self.distances_km, &top.distances_km);
+        overlay(&mut self.durations_secs, &top.durations_secs);
+    }
+}
+
+/// Table rows keyed by node id, each value times `scale`; null cells are left out.
+fn from_rows(
+    rows: &[Vec<Option<f64>>],
+    sources: &[String],
+    destinations: &[String],
+    name: &str,
+    scale: f64,
+) -> Result<HashMap<String, HashMap<String, f64>>, PricingError> {
+    if rows.len() != sources.len() {
+        return Err(parse_error(format!("{} has {} rows for {} sources", name, rows.len(), sources.len())));
+    }
+    let mut table = HashMap::new();
+    for (from, row) in sources.iter().zip(rows) {
+        if row.len() != destinations.len() {
+            return Err(parse_error(format!(
+                "{} row of {} has {} values for {} destinations", name, from, row.len(), destinations.len()
+            )));
+        }
+        for (to, value) in destinations.iter().zip(row) {
+            insert(&mut table, from, to, value.map(|value| value * scale));
+        }
+    }
+    Ok(table)
+}
+
+fn node_at<'a>(nodes: &'a [String], index: usize, role: &str) -> Result<&'a String, PricingError> {
+    nodes.get(index)
+        .ok_or_else(|| parse_error(format!("{} index {} has no node id, only {} given", role, index, nodes.len())))
+}
+
+fn insert(table: &mut HashMap<String, HashMap<String, f64>>, from: &str, to: &str, value: Option<f64>) {
+    if let Some(value) = value {
+        table.entry(from.to_string()).or_default().insert(to.to_string(), value);
+    }
+}
+
+fn overlay(base: &mut HashMap<String, HashMap<String, f64>>, top: &HashMap<String, HashMap<String, f64>>) {
+    for (from, row) in top {
+        base.entry(from.clone()).or_default().extend(row.iter().map(|(to, value)| (to.clone(), *value)));
+    }
+}
+
+pub(crate) fn parse_error(message: String) -> PricingError {
+    PricingError::Parse(serde::de::Error::custom(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(format: MatrixFormat, sources: &[&str]) -> MatrixInput {
+        MatrixInput {
+            format,
+            sources: sources.iter().map(|id| id.to_string()).collect(),
+            ..MatrixInput::default()
+        }
+    }
+
+    #[test]
+    fn osrm_rows_are_keyed_by_source_and_scaled_to_km() {
+        let input = table(MatrixFormat::OsrmTable, &["W_1", "C_1"]);
+        let text = r#"{"code":"Ok","distances":[[0,1500],[1400,null]],"durations":[[0,120],[110,0]]}"#;
+
+        let matrix = input.read(text).unwrap();
+
+        assert!(matrix.distance_km("W_1", "C_1").is_some_and(|km| (km - 1.5).abs() < 1e-12));
+        assert!(matrix.distance_km("C_1", "W_1").is_some_and(|km| (km - 1.4).abs() < 1e-12));
+        assert_eq!(matrix.distance_km("C_1", "C_1"), None);
+        assert_eq!(matrix.duration_secs("C_1", "W_1"), Some(110.0));
+    }
+
+    #[test]
+    fn osrm_errors_and_short_tables_are_parse_errors() {
+        let input = table(MatrixFormat::OsrmTable, &["W_1", "C_1"]);
+        assert!(matches!(input.read(r#"{"code":"NoTable","message":"no"}"#), Err(PricingError::Parse(_))));
+        assert!(matches!(input.read(r#"{"durations":[[0,1]]}"#), Err(PricingError::Parse(_))));
+    }
+
+    #[test]
+    fn valhalla_cells_use_their_own_indices() {
+        let mut input = table(MatrixFormat::Valhalla, &["W_1"]);
+        input.destinations = vec!["C_1".to_string(), "C_2".to_string()];
+        let text = r#"{"sources_to_targets":[[
+            {"from_index":0,"to_index":1,"distance":2.5,"time":300},
+            {"from_index":0,"to_index":0,"distance":1.0,"time":null}
+        ]]}"#;
+
+        let matrix = input.read(text).unwrap();
+
+        assert_eq!(matrix.distance_km("W_1", "C_2"), Some(2.5));
+        assert_eq!(matrix.duration_secs("W_1", "C_2"), Some(300.0));
+        assert_eq!(matrix.distance_km("W_1", "C_1"), Some(1.0));
+        assert_eq!(matrix.duration_secs("W_1", "C_1"), None);
+    }
+
+    #[test]
+    fn csv_rows_convert_meters_and_allow_empty_values() {
+        let input = table(MatrixFormat::Csv, &[]);
+        let text = "from,to,meters,seconds\nW_1,C_1,2500,180\nC_1,W_1,,200\n";
+
+        let matrix = input.read(text).unwrap();
+
+        assert_eq!(matrix.distance_km("W_1", "C_1"), Some(2.5));
+        assert_eq!(matrix.duration_secs("W_1", "C_1"), Some(180.0));
+        assert_eq!(matrix.distance_km("C_1", "W_1"), None);
+        assert_eq!(matrix.duration_secs("C_1", "W_1"), Some(200.0));
+    }
+
+    #[test]
+    fn inline_entries_win_over_the_file() {
+        let mut base = table(MatrixFormat::Csv, &[]).read("from,to,meters,seconds\nW_1,C_1,2500,180\n").unwrap();
+        let mut top = TravelMatrix::default();
+        top.distances_km.entry("W_1".to_string()).or_default().insert("C_1".to_string(), 3.0);
+
+        base.overlay(&top);
+
+        assert_eq!(base.distance_km("W_1", "C_1"), Some(3.0));
+        assert_eq!(base.duration_secs("W_1", "C_1"), Some(180.0));
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, TimeDelta, Utc};
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeDelta, Utc};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,7 +15,7 @@ pub struct Customer {
 }
 
 mod datetime_serde {
-    use chrono::{DateTime, TimeZone, Utc};
+    use chrono::{DateTime, Utc};
     use serde::{self, Deserialize, Deserializer, Serializer};
     
     pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
@@ -41,6 +41,34 @@ pub struct Warehouse {
     pub id: i64,
     pub lat: f64,
     pub lng: f64,
+    /// Release time of this warehouse's vehicles; `departure_hour` when absent.
+    #[serde(default)]
+    pub departure: Option<DepartureSpec>,
+}
+
+/// When vehicles may leave a warehouse: exactly `at`, or any time from
+/// `earliest` to `latest`. An open `earliest` is the planning departure.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DepartureSpec {
+    #[serde(default)]
+    pub at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub earliest: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub latest: Option<DateTime<Utc>>,
+}
+
+/// Departures of one vehicle type; they take precedence over the
+/// warehouses' own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VehicleType {
+    pub id: String,
+    /// Departure from any warehouse without an entry in `warehouse_departures`.
+    #[serde(default)]
+    pub departure: Option<DepartureSpec>,
+    /// Keyed by warehouse id, like `dual_values` by customer id.
+    #[serde(default)]
+    pub warehouse_departures: std::collections::HashMap<String, DepartureSpec>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,10 +81,315 @@ pub struct InputData {
     pub max_capacity: f64,
     pub cost_per_km: f64,
     pub speed_kmh: f64,
+    /// Speeds by time of day; `speed_kmh` applies where it has no periods.
+    #[serde(default)]
+    pub speed_profile: Option<SpeedProfile>,
+    /// Road distances and durations between nodes; arcs it leaves out use
+    /// haversine distance and `speed_kmh`.
+    #[serde(default)]
+    pub matrix: Option<MatrixInput>,
+    /// Straight-line distance of arcs without a matrix distance.
+    #[serde(default)]
+    pub distance_metric: DistanceMetric,
+    /// Road-over-straight-line factors for those arcs.
+    #[serde(default)]
+    pub detour: DetourFactors,
+    /// Local road network to compute the travel matrix from. Entries of
+    /// `matrix` take precedence over it.
+    #[serde(default)]
+    pub road_graph: Option<RoadGraphInput>,
+    /// Service time per customer in minutes; `service_time_secs` overrides it.
+    #[serde(default)]
     pub service_time: i64,
+    #[serde(default)]
+    pub service_time_secs: Option<i64>,
+    /// Granularity of travel times: each arc is truncated to a multiple of
+    /// this many seconds. Whole minutes by default, as the calculator uses.
+    #[serde(default = "default_time_resolution_secs")]
+    pub time_resolution_secs: i64,
     pub departure_hour: u32,
+    /// IANA name of the planning timezone, e.g. `Europe/Berlin`, for
+    /// `departure_hour` and output times; +06:00 when absent.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub vehicle_types: Vec<VehicleType>,
+    /// Id of the entry in `vehicle_types` this pricing run is for.
+    #[serde(default)]
+    pub vehicle_type: Option<String>,
+    #[serde(default)]
+    pub departure_mode: DepartureMode,
     pub allow_violate_time_window: bool, // Add this field
     pub penalties: PenaltyParams,  // Add this
+    /// Routes with up to this many customers, at most 15, are resequenced
+    /// exactly.
+    #[serde(default = "default_exact_sequencing_max_stops")]
+    pub exact_sequencing_max_stops: usize,
+    /// Cost charged once per route, e.g. vehicle hire. Reported in each
+    /// route's cost breakdown; reduced costs leave it out.
+    #[serde(default)]
+    pub fixed_vehicle_cost: f64,
+    /// Cost model for finished routes; the in-process model by default.
+    #[serde(default)]
+    pub evaluator: EvaluatorConfig,
+    /// What to do with a route the evaluator fails to cost.
+    #[serde(default)]
+    pub evaluator_fallback: EvaluatorFallback,
+    #[serde(default)]
+    pub route_cache: RouteCacheConfig,
+    /// Enables the consistency check of the returned route: the solve
+    /// fails if its edge and evaluator costs differ by more than this.
+    #[serde(default)]
+    pub consistency_tolerance: Option<f64>,
+    /// Seconds the labeling may run before the solve fails.
+    #[serde(default)]
+    pub time_limit_secs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EvaluatorConfig {
+    #[default]
+    Internal,
+    /// External trip calculator, fed its JSON input on stdin. A null
+    /// `timeout_secs` waits forever.
+    Subprocess {
+        path: String,
+        #[serde(default = "default_calculator_args")]
+        args: Vec<String>,
+        #[serde(default = "default_calculator_timeout")]
+        timeout_secs: Option<f64>,
+        #[serde(default)]
+        protocol: CalculatorProtocol,
+    },
+    /// Deterministic costs for tests.
+    Mock {
+        #[serde(default)]
+        fixed_cost: f64,
+        #[serde(default)]
+        cost_per_stop: f64,
+    },
+}
+
+pub fn default_calculator_args() -> Vec<String> {
+    vec!["--input".to_string(), "/dev/stdin".to_string()]
+}
+
+pub fn default_calculator_timeout() -> Option<f64> {
+    Some(30.0)
+}
+
+/// Memoization of trip costs. Setting `file` enables the cache and keeps
+/// it between runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+/// How routes are handed to an external calculator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalculatorProtocol {
+    /// One process per route, reading the full input document.
+    #[default]
+    PerRoute,
+    /// One process for the whole run. Its first stdin line holds the
+    /// locations and cost parameters, every further line a route as
+    /// `{"path": [..], "departure": ".."}`. It answers each route, in order,
+    /// with one line holding the trip cost or `{"error": ".."}`.
+    JsonLines,
+}
+
+/// Arc data keyed by node id, `from` then `to`, e.g.
+/// `distances_km["W_1"]["C_3"]`. Need not be symmetric.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TravelMatrix {
+    #[serde(default)]
+    pub distances_km: std::collections::HashMap<String, std::collections::HashMap<String, f64>>,
+    #[serde(default)]
+    pub durations_secs: std::collections::HashMap<String, std::collections::HashMap<String, f64>>,
+}
+
+/// A travel matrix given inline, read from `file`, or both; inline
+/// entries win.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MatrixInput {
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub format: MatrixFormat,
+    /// Node ids of a table's rows, for the OSRM and Valhalla formats.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Node ids of a table's columns; `sources` when empty.
+    #[serde(default)]
+    pub destinations: Vec<String>,
+    /// Fail unless every arc of the graph has a distance and a duration,
+    /// instead of falling back to `distance_metric` for the missing ones.
+    /// On by default when there is a `file`.
+    #[serde(default)]
+    pub require_complete: Option<bool>,
+    #[serde(flatten)]
+    pub matrix: TravelMatrix,
+}
+
+/// Road network as a CSV edge list with the header
+/// `from,to,from_lat,from_lng,to_lat,to_lng,length_m,speed_class,oneway`.
+/// Edges run both ways unless `oneway` is `true`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoadGraphInput {
+    pub file: String,
+    /// km/h per speed class; classes not listed drive at `speed_kmh`.
+    #[serde(default)]
+    pub speed_classes: std::collections::HashMap<String, f64>,
+    /// Farthest a location may be from its nearest road node. The way
+    /// there is driven in a straight line at `speed_kmh`.
+    #[serde(default = "default_max_snap_km")]
+    pub max_snap_km: f64,
+}
+
+fn default_max_snap_km() -> f64 {
+    1.0
+}
+
+/// Layout of a matrix `file`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatrixFormat {
+    /// `TravelMatrix` JSON.
+    #[default]
+    Json,
+    /// OSRM `table` response: `distances` in metres and `durations` in
+    /// seconds, one row per source. Unroutable pairs are null.
+    OsrmTable,
+    /// Valhalla matrix response: `sources_to_targets` cells with
+    /// `distance` in kilometres and `time` in seconds.
+    Valhalla,
+    /// CSV with a `from,to,meters,seconds` header; either value may be empty.
+    Csv,
+}
+
+/// Earth model for straight-line distances.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    /// Great circle on a sphere of radius 6371 km.
+    #[default]
+    Haversine,
+    /// Vincenty's iteration on the WGS84 ellipsoid; Karney's method for
+    /// nearly antipodal points, where it does not converge.
+    Vincenty,
+    /// Karney's geodesic on the WGS84 ellipsoid.
+    Karney,
+}
+
+/// Kind of arc, by the nodes it joins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArcClass {
+    FromWarehouse,
+    BetweenCustomers,
+    ToWarehouse,
+}
+
+/// Road distance of an arc over its straight-line distance: the factor of
+/// the first region holding the arc's start, or `factor` outside every
+/// region, times the factor of its class.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetourFactors {
+    #[serde(default = "default_detour_factor")]
+    pub factor: f64,
+    #[serde(default)]
+    pub regions: Vec<DetourRegion>,
+    /// Classes not listed keep a factor of 1.
+    #[serde(default)]
+    pub arc_classes: std::collections::HashMap<ArcClass, f64>,
+}
+
+impl Default for DetourFactors {
+    fn default() -> Self {
+        Self { factor: default_detour_factor(), regions: Vec::new(), arc_classes: Default::default() }
+    }
+}
+
+fn default_detour_factor() -> f64 {
+    1.0
+}
+
+/// Latitude/longitude box with its own detour factor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetourRegion {
+    pub id: String,
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lng: f64,
+    pub max_lng: f64,
+    pub factor: f64,
+}
+
+/// Piecewise-constant speeds by local time of day. Each period runs from
+/// its `start` to the next one's, the last one past midnight into the
+/// first. A leg that crosses a period boundary drives on at the new speed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpeedProfile {
+    #[serde(default)]
+    pub periods: Vec<SpeedPeriod>,
+    /// Areas with their own periods. A leg uses the first zone that holds
+    /// its start, or `periods` if there is none.
+    #[serde(default)]
+    pub zones: Vec<SpeedZone>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedPeriod {
+    /// Local time of day, e.g. `07:30:00`.
+    pub start: NaiveTime,
+    pub speed_kmh: f64,
+}
+
+/// Latitude/longitude box with its own speed periods.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedZone {
+    pub id: String,
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lng: f64,
+    pub max_lng: f64,
+    pub periods: Vec<SpeedPeriod>,
+}
+
+/// How a route's departure is chosen within its warehouse's window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepartureMode {
+    /// Leave at the start of the window.
+    #[default]
+    Earliest,
+    /// Leave as late as the window and the customers' windows allow.
+    LatestFeasible,
+    /// Leave late enough to cut as much waiting as possible, but no later.
+    MinWaiting,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvaluatorFallback {
+    /// Skip the route and keep searching.
+    #[default]
+    Skip,
+    /// Price the route with the graph's own edge costs.
+    EdgeCost,
+}
+
+fn default_time_resolution_secs() -> i64 {
+    60
+}
+
+fn default_exact_sequencing_max_stops() -> usize {
+    12
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,14 +402,346 @@ pub struct PenaltyParams {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PathResult {
     pub path: Vec<String>,
+    /// When the vehicle leaves its warehouse, in planning-zone local time.
+    pub departure: DateTime<FixedOffset>,
+    /// Includes the waiting penalty unless `departure_mode` is `earliest`.
+    pub reduced_cost: f64,
+    pub cost: f64,
+    pub capacity: f64,
+    /// False if the returned order breaks a constraint; only possible when
+    /// `allow_violate_time_window` is set.
+    pub feasible: bool,
+    pub violations: Vec<Violation>,
+    /// One entry per node of `path`, warehouses included.
+    pub schedule: Vec<StopSchedule>,
+    pub cost_breakdown: CostBreakdown,
+    /// Where `cost` came from.
+    pub cost_source: CostSource,
+    /// Straight-line distance used for arcs without a matrix distance.
+    pub distance_metric: DistanceMetric,
+    pub stats: SearchStats,
+    /// Present when the consistency check is enabled and the evaluator
+    /// costed the route.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consistency: Option<ConsistencyReport>,
+}
+
+/// Result of the `evaluate` command for one given route.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteReport {
+    pub path: Vec<String>,
+    pub feasible: bool,
+    /// Under the input's duals.
     pub reduced_cost: f64,
     pub cost: f64,
+    pub cost_source: CostSource,
+    /// Straight-line distance used for arcs without a matrix distance.
+    pub distance_metric: DistanceMetric,
+    /// Why the evaluator could not cost the route, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evaluator_error: Option<String>,
     pub capacity: f64,
+    pub violations: Vec<Violation>,
+    pub schedule: Vec<StopSchedule>,
+    pub cost_breakdown: CostBreakdown,
+}
+
+/// KPIs of a whole plan, from the `plan-report` command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanReport {
+    pub coverage: Coverage,
+    /// Straight-line distance used for arcs without a matrix distance.
+    pub distance_metric: DistanceMetric,
+    pub vehicles: usize,
+    /// Routes starting at each warehouse.
+    pub vehicles_per_warehouse: std::collections::BTreeMap<String, usize>,
+    pub infeasible_routes: usize,
+    pub total_distance_km: f64,
+    pub total_duration_minutes: f64,
+    pub total_cost: f64,
+    pub total_reduced_cost: f64,
+    pub total_load: f64,
+    /// Total load over the capacity of all vehicles used.
+    pub utilisation: f64,
+    pub total_waiting_minutes: f64,
+    pub total_late_arrival_minutes: f64,
+    pub total_late_service_minutes: f64,
+    pub routes: Vec<RouteSummary>,
+}
+
+/// Which customers a plan serves, as node ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coverage {
+    pub customers: usize,
+    pub served: usize,
+    pub unserved: Vec<String>,
+    /// Customers visited more than once, by one route or several.
+    pub duplicated: Vec<String>,
+    /// Nodes in the routes that are not part of the instance.
+    pub unknown: Vec<String>,
+}
+
+/// One row of the per-route table of a plan report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSummary {
+    /// Position of the route in the plan.
+    pub route: usize,
+    pub warehouse: String,
+    pub path: Vec<String>,
+    pub stops: usize,
+    pub distance_km: f64,
+    /// From leaving the warehouse to arriving back.
+    pub duration_minutes: f64,
+    pub load: f64,
+    /// Load over `max_capacity`.
+    pub utilisation: f64,
+    pub waiting_minutes: f64,
+    pub late_arrival_minutes: f64,
+    pub late_service_minutes: f64,
+    pub cost: f64,
+    pub reduced_cost: f64,
+    pub feasible: bool,
+    /// Number of violated constraints.
+    pub violations: usize,
+}
+
+/// Every problem found in an input, see `InputData::validate`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub issues: Vec<ValidationIssue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    InvalidDate,
+    UnknownTimezone,
+    ConflictingDeparture,
+    Missing,
+    DuplicateId,
+    InvertedWindow,
+    Negative,
+    NonPositive,
+    OutOfRange,
+    UnknownDual,
+    UnknownWarehouse,
+    UnknownVehicleType,
+    UnknownNode,
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    /// Path of the offending value in the input, e.g. `customers[3].window_end`.
+    pub field: String,
+    /// Node id of the customer or warehouse concerned.
+    pub id: Option<String>,
+    pub message: String,
+}
+
+/// Customers that cannot be part of any route, from the `diagnose` command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InfeasibilityReport {
+    /// Number of customers checked.
+    pub customers: usize,
+    pub unreachable: Vec<CustomerDiagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomerDiagnostic {
+    pub node: String,
+    pub reasons: Vec<InfeasibilityReason>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonKind {
+    CapacityExceedsVehicle,
+    WindowTooShort,
+    /// Reported per warehouse, and only if every warehouse is too late.
+    UnreachableFromWarehouse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InfeasibilityReason {
+    pub kind: ReasonKind,
+    pub warehouse: Option<String>,
+    pub message: String,
+}
+
+/// Why the evaluator's cost of a route differs from the edge cost the
+/// labeling found it at, by cause:
+/// `difference = reordering + distance + penalties + rounding + residual`.
+///
+/// Penalties only explain a gap if the evaluator charges them, which is
+/// assumed when its cost beyond distance is closer to the penalties it
+/// reports than to nothing. The other fields are for reference.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// `calculate_path_cost` of the labeling's order: its edges' distances,
+    /// from the matrix or the distance metric, without penalties.
+    pub edge_cost: f64,
+    /// The evaluator's `total_cost` of the returned order.
+    pub evaluator_cost: f64,
+    /// `evaluator_cost - edge_cost`.
+    pub difference: f64,
+    /// Edge cost of the returned order minus that of the labeling's order.
+    pub reordering: f64,
+    /// Cost of the evaluator's distance beyond the returned order's edges,
+    /// e.g. a road factor.
+    pub distance: f64,
+    /// Penalties of the internal schedule with exact travel times, when the
+    /// evaluator charges penalties.
+    pub penalties: f64,
+    /// How much truncating travel times to the time resolution adds to
+    /// those penalties.
+    pub rounding: f64,
+    /// Whatever the causes above leave unexplained.
+    pub residual: f64,
+    /// Travel minutes lost by truncating edge travel times to the time resolution.
+    pub rounding_minutes: f64,
+    /// Penalties of the internal schedule.
+    pub internal_penalties: f64,
+    /// Penalties of the internal schedule with exact travel times: matrix
+    /// durations where given, otherwise the speed or speed profile.
+    pub unrounded_penalties: f64,
+    /// Waiting and time window penalties reported by the evaluator.
+    pub evaluator_penalties: f64,
+    pub tolerance: f64,
+    pub within_tolerance: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostSource {
+    Evaluator,
+    EdgeCost,
+}
+
+/// Trip cost evaluator usage over the whole search, not just the returned route.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchStats {
+    /// Improving routes handed to the evaluator.
+    pub evaluated_routes: usize,
+    pub evaluator_failures: usize,
+    /// Distinct reasons of those failures, in the order they first occurred.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evaluator_errors: Vec<String>,
+    /// Route cache counts, if the cache is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Why the cache file could not be read or written; the cache then
+    /// only lasts for the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_error: Option<String>,
+}
+
+/// Components of a route's cost and the duals it collects.
+///
+/// The fields up to `dual_total` break down the graph's edge costs, which
+/// `reduced_cost` is based on. Penalties and the fixed vehicle cost are
+/// reported but, apart from waiting when `departure_mode` is not
+/// `earliest`, not priced, so `reduced_cost = priced_cost - dual_total`. `evaluated` breaks down the
+/// route's `cost` instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostBreakdown {
+    pub distance_cost: f64,
+    pub fixed_vehicle_cost: f64,
+    pub waiting_penalty: f64,
+    pub late_arrival_penalty: f64,
+    pub late_service_penalty: f64,
+    /// Distance cost, plus the waiting penalty unless `departure_mode` is
+    /// `earliest`: the part the labeling prices.
+    pub priced_cost: f64,
+    /// All components, penalties included.
+    pub total_cost: f64,
+    /// Dual value of each visited customer, in visiting order.
+    pub duals: Vec<DualContribution>,
+    pub dual_total: f64,
+    /// Present once the route has a `cost`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evaluated: Option<EvaluatedCost>,
+}
+
+/// How a route's `cost` is made up, from the evaluator or, for the edge
+/// cost fallback, from the graph: `cost = distance_cost + other_cost`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluatedCost {
+    pub distance_km: f64,
+    /// `distance_km` at `cost_per_km`.
+    pub distance_cost: f64,
+    /// Whatever else is charged.
+    pub other_cost: f64,
+    /// Reported beside `cost`, not part of it.
+    pub waiting_penalty: f64,
+    /// Late arrival and late service together; also not part of `cost`.
+    pub time_window_penalty: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DualContribution {
+    pub node: String,
+    pub dual: f64,
+}
+
+/// Timing and running totals at one stop of a route, with times in the
+/// planning timezone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StopSchedule {
+    pub node: String,
+    pub arrival: DateTime<FixedOffset>,
+    pub waiting_minutes: f64,
+    pub service_start: DateTime<FixedOffset>,
+    pub service_end: DateTime<FixedOffset>,
+    pub departure: DateTime<FixedOffset>,
+    /// Load delivered up to and including this stop.
+    pub load: f64,
+    /// Distance driven up to this stop.
+    pub distance_km: f64,
+    pub late_arrival_minutes: f64,
+    pub late_service_minutes: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    StartWarehouse,
+    ReturnWarehouse,
+    UnknownNode,
+    MissingArc,
+    DuplicateCustomer,
+    MaxStops,
+    Capacity,
+    LateArrival,
+    LateService,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// Node the violation was found at, if it belongs to one.
+    pub node: Option<String>,
+    pub message: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct EdgeData {
+    pub distance_km: f64,
     pub cost: f64,
+    /// At `speed_kmh`; with a speed profile the travel time depends on the
+    /// departure and comes from `PricingProblem::arrival`.
     pub travel_time: TimeDelta,
     pub reduced_cost: f64,
+    /// Speed zone of the arc's start, index into the travel model's profiles.
+    pub speed_zone: usize,
+    /// `travel_time` came from the duration matrix and holds at any departure.
+    pub fixed_travel_time: bool,
 }
\ No newline at end of file
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use crate::error::PricingError;
+use crate::models::{Coverage, PlanReport, RouteReport, RouteSummary};
+use crate::pricing::PricingProblem;
+use crate::travel_time::minutes;
+
+impl PricingProblem {
+    /// KPIs of a whole plan: every route is checked with `evaluate_paths`,
+    /// then coverage and totals are taken over all of them.
+    pub fn plan_report(&self, paths: &[Vec<String>]) -> Result<PlanReport, PricingError> {
+        let reports = self.evaluate_paths(paths)?;
+        let routes: Vec<RouteSummary> = reports.iter()
+            .enumerate()
+            .map(|(index, report)| self.route_summary(index, report))
+            .collect();
+
+        let mut visits: BTreeMap<&str, usize> = self.customer_ids().map(|id| (id, 0)).collect();
+        let mut unknown = Vec::new();
+        for node in paths.iter().flatten().filter(|node| self.warehouse(node).is_none()) {
+            match visits.get_mut(node.as_str()) {
+                Some(count) => *count += 1,
+                None => unknown.push(node.clone()),
+            }
+        }
+
+        let mut vehicles_per_warehouse = BTreeMap::new();
+        for route in &routes {
+            *vehicles_per_warehouse.entry(route.warehouse.clone()).or_insert(0) += 1;
+        }
+
+        let sum = |f: fn(&RouteSummary) -> f64| routes.iter().map(f).sum::<f64>();
+        let total_load = sum(|r| r.load);
+        let capacity = self.max_capacity() * routes.len() as f64;
+
+        Ok(PlanReport {
+            coverage: Coverage {
+                customers: visits.len(),
+                served: visits.values().filter(|&&count| count > 0).count(),
+                unserved: visits.iter().filter(|(_, &count)| count == 0).map(|(id, _)| id.to_string()).collect(),
+                duplicated: visits.iter().filter(|(_, &count)| count > 1).map(|(id, _)| id.to_string()).collect(),
+                unknown,
+            },
+            distance_metric: self.distance_metric(),
+            vehicles: routes.len(),
+            vehicles_per_warehouse,
+            infeasible_routes: routes.iter().filter(|r| !r.feasible).count(),
+            total_distance_km: sum(|r| r.distance_km),
+            total_duration_minutes: sum(|r| r.duration_minutes),
+            total_cost: sum(|r| r.cost),
+            total_reduced_cost: sum(|r| r.reduced_cost),
+            total_load,
+            utilisation: if capacity > 0.0 { total_load / capacity } else { 0.0 },
+            total_waiting_minutes: sum(|r| r.waiting_minutes),
+            total_late_arrival_minutes: sum(|r| r.late_arrival_minutes),
+            total_late_service_minutes: sum(|r| r.late_service_minutes),
+            routes,
+        })
+    }
+
+    fn route_summary(&self, index: usize, report: &RouteReport) -> RouteSummary {
+        let schedule = &report.schedule;
+        let duration_minutes = match (schedule.first(), schedule.last()) {
+            (Some(first), Some(last)) => minutes(last.arrival - first.departure),
+            _ => 0.0,
+        };
+
+        RouteSummary {
+            route: index,
+            warehouse: report.path.first().cloned().unwrap_or_default(),
+            path: report.path.clone(),
+            stops: report.path.iter().filter(|node| self.customer(node).is_some()).count(),
+            distance_km: schedule.last().map_or(0.0, |stop| stop.distance_km),
+            duration_minutes,
+            load: report.capacity,
+            utilisation: if self.max_capacity() > 0.0 { report.capacity / self.max_capacity() } else { 0.0 },
+            waiting_minutes: schedule.iter().map(|stop| stop.waiting_minutes).sum(),
+            late_arrival_minutes: schedule.iter().map(|stop| stop.late_arrival_minutes).sum(),
+            late_service_minutes: schedule.iter().map(|stop| stop.late_service_minutes).sum(),
+            cost: report.cost,
+            reduced_cost: report.reduced_cost,
+            feasible: report.feasible,
+            violations: report.violations.len(),
+        }
+    }
+}
+
+/// Writes the per-route table as CSV, one row per route with its path as
+/// comma-separated node ids.
+pub fn write_route_csv<W: Write>(writer: W, routes: &[RouteSummary]) -> csv::Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record([
+        "route", "warehouse", "path", "stops", "distance_km", "duration_minutes", "load", "utilisation",
+        "waiting_minutes", "late_arrival_minutes", "late_service_minutes", "cost", "reduced_cost",
+        "feasible", "violations",
+    ])?;
+    for r in routes {
+        csv.write_record([
+            r.route.to_string(),
+            r.warehouse.clone(),
+            r.path.join(","),
+            r.stops.to_string(),
+            r.distance_km.to_string(),
+            r.duration_minutes.to_string(),
+            r.load.to_string(),
+            r.utilisation.to_string(),
+            r.waiting_minutes.to_string(),
+            r.late_arrival_minutes.to_string(),
+            r.late_service_minutes.to_string(),
+            r.cost.to_string(),
+            r.reduced_cost.to_string(),
+            r.feasible.to_string(),
+            r.violations.to_string(),
+        ])?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_pricing, test_input};
+
+    fn route(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn plan() -> Vec<Vec<String>> {
+        vec![
+            route(&["W_1", "C_1", "C_2", "W_1"]),
+            route(&["W_1", "C_2", "C_3", "W_1"]),
+            route(&["W_2", "C_4", "C_404", "W_2"]),
+        ]
+    }
+
+    #[test]
+    fn coverage_counts_every_customer_once() {
+        let pricing = build_pricing(test_input()).unwrap();
+
+        let report = pricing.plan_report(&plan()).unwrap();
+
+        let coverage = &report.coverage;
+        assert_eq!(coverage.customers, 20);
+        assert_eq!(coverage.served, 4);
+        assert_eq!(coverage.unserved.len(), 16);
+        assert!(!coverage.unserved.contains(&"C_4".to_string()));
+        assert_eq!(coverage.duplicated, ["C_2"]);
+        assert_eq!(coverage.unknown, ["C_404"]);
+        assert_eq!(report.vehicles, 3);
+        assert_eq!(report.vehicles_per_warehouse.get("W_1"), Some(&2));
+        assert_eq!(report.vehicles_per_warehouse.get("W_2"), Some(&1));
+        assert_eq!(report.infeasible_routes, 1);
+    }
+
+    #[test]
+    fn totals_and_utilisation_add_up_over_routes() {
+        let pricing = build_pricing(test_input()).unwrap();
+
+        let report = pricing.plan_report(&plan()).unwrap();
+
+        let sum = |f: fn(&RouteSummary) -> f64| report.routes.iter().map(f).sum::<f64>();
+        assert!((report.total_distance_km - sum(|r| r.distance_km)).abs() < 1e-9);
+        assert!((report.total_waiting_minutes - sum(|r| r.waiting_minutes)).abs() < 1e-9);
+        // C_1 and C_2 load 8 of a capacity of 500
+        assert_eq!(report.routes[0].load, 8.0);
+        assert_eq!(report.routes[0].utilisation, 8.0 / 500.0);
+        assert_eq!(report.routes[1].stops, 2);
+        assert!(report.routes[0].duration_minutes > 0.0);
+        assert_eq!(report.total_load, sum(|r| r.load));
+        assert_eq!(report.utilisation, report.total_load / (3.0 * 500.0));
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_row_per_route() {
+        let pricing = build_pricing(test_input()).unwrap();
+        let report = pricing.plan_report(&plan()).unwrap();
+
+        let mut out = Vec::new();
+        write_route_csv(&mut out, &report.routes).unwrap();
+
+        let mut rows = csv::Reader::from_reader(out.as_slice());
+        let header = rows.headers().unwrap().clone();
+        assert_eq!(&header[0], "route");
+        assert_eq!(header.len(), 15);
+        let records: Vec<csv::StringRecord> = rows.records().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(&records[0][2], "W_1,C_1,C_2,W_1");
+        assert_eq!(&records[2][13], "false");
+    }
+}
@@ -1,34 +1,69 @@
 use petgraph::graph::{DiGraph, NodeIndex};
-use petgraph::visit::{EdgeRef, IntoEdges, IntoNodeReferences};
-use chrono::{DateTime, TimeDelta, Utc};
+use petgraph::visit::EdgeRef;
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta, Utc};
 use std::collections::{HashMap, VecDeque};
-use std::f64::consts::PI;
-use tempfile::NamedTempFile;
-use std::process::Command;
-use std::io::Write;
-use crate::models::{Customer, Warehouse, EdgeData, PathResult};
-use crate::models::PenaltyParams;
+use std::time::{Duration, Instant};
+use crate::models::{ArcClass, Customer, DistanceMetric, Warehouse, EdgeData, PathResult, SpeedProfile, TravelMatrix, VehicleType};
+use crate::models::{CostSource, DepartureMode, EvaluatorConfig, EvaluatorFallback, PenaltyParams, RouteCacheConfig, SearchStats};
+use crate::models::{IssueKind, ValidationIssue, ValidationReport};
+use crate::error::PricingError;
+use crate::evaluation::RouteEvaluation;
+use crate::evaluator::{build_evaluator, TripCostEvaluator};
+use crate::geodesy::DistanceModel;
+use crate::timezone::PlanningZone;
+use crate::travel_time::TravelModel;
+use crate::trip_cost::{Location, TripCostModel};
 
 const EARTH_RADIUS_KM: f64 = 6371.0;
 
+/// Partial path state kept by the labeling: (reduced cost, ready time, load, path).
+type Label = (f64, DateTime<Utc>, f64, Vec<String>);
+/// When vehicles may leave one warehouse.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DepartureWindow {
+    pub earliest: DateTime<Utc>,
+    /// Open when the input gives no latest departure.
+    pub latest: Option<DateTime<Utc>>,
+}
+
+/// Improving route waiting for its trip cost.
+struct Candidate {
+    /// Customer order as found by the labeling.
+    labeling_path: Vec<String>,
+    path: Vec<String>,
+    departure: DateTime<Utc>,
+    evaluation: RouteEvaluation,
+}
 
 pub struct PricingProblem {
     graph: DiGraph<String, EdgeData>,
     node_indices: HashMap<String, NodeIndex>,
     customers: HashMap<String, Customer>,
     warehouses: HashMap<String, Warehouse>,
+    dual_values: HashMap<String, f64>,
     max_stops: usize,
     max_capacity: f64,
     cost_per_km: f64,
-    speed_kmh: f64,
-    service_time: i64,
-    planning_date: String, 
-    departure_hour: u32, 
+    travel: TravelModel,
+    matrix: TravelMatrix,
+    distance: DistanceModel,
+    service_duration: TimeDelta,
+    departure: DateTime<Utc>,
+    /// Per warehouse node, when its vehicles may leave.
+    departure_windows: HashMap<String, DepartureWindow>,
+    departure_mode: DepartureMode,
+    zone: PlanningZone,
     allow_violate_time_window: bool,
     penalties: PenaltyParams,  // Add this
+    exact_sequencing_max_stops: usize,
+    fixed_vehicle_cost: f64,
+    evaluator: Box<dyn TripCostEvaluator>,
+    evaluator_fallback: EvaluatorFallback,
+    consistency_tolerance: Option<f64>,
 }
 
 impl PricingProblem {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         customers: Vec<Customer>,
         warehouses: Vec<Warehouse>,
@@ -37,20 +72,58 @@ impl PricingProblem {
         max_capacity: f64,
         cost_per_km: f64,
         speed_kmh: f64,
-        service_time: i64,
+        speed_profile: Option<SpeedProfile>,
+        matrix: TravelMatrix,
+        distance: DistanceModel,
+        service_duration: TimeDelta,
+        time_resolution_secs: i64,
         planning_date: String, 
         departure_hour: u32, 
+        timezone: Option<&str>,
+        vehicle_type: Option<&VehicleType>,
+        departure_mode: DepartureMode,
         allow_violate_time_window: bool,
         penalties: PenaltyParams, 
-    ) -> Self {
+        exact_sequencing_max_stops: usize,
+        fixed_vehicle_cost: f64,
+        evaluator: &EvaluatorConfig,
+        evaluator_fallback: EvaluatorFallback,
+        route_cache: &RouteCacheConfig,
+        consistency_tolerance: Option<f64>,
+    ) -> Result<Self, PricingError> {
+        let invalid = |kind: IssueKind, field: &str, message: String| PricingError::Validation(ValidationReport {
+            valid: false,
+            issues: vec![ValidationIssue { kind, field: field.to_string(), id: None, message }],
+        });
+        let zone = PlanningZone::from_name(timezone)
+            .map_err(|message| invalid(IssueKind::UnknownTimezone, "timezone", message))?;
+        let travel = TravelModel::new(speed_kmh, time_resolution_secs, zone, speed_profile);
+        let departure = NaiveDate::parse_from_str(&planning_date, "%Y-%m-%d").ok()
+            .and_then(|date| zone.at_hour(date, departure_hour))
+            .ok_or_else(|| invalid(IssueKind::InvalidDate, "planning_date", format!(
+                "No departure at hour {} of '{}' in the planning timezone", departure_hour, planning_date
+            )))?;
+
         let mut graph = DiGraph::new();
         let mut node_indices = HashMap::new();
         let mut customer_map = HashMap::new();
         let mut warehouse_map = HashMap::new();
+        let mut departure_windows = HashMap::new();
 
         // Add warehouses
         for wh in warehouses {
             let node_id = format!("W_{}", wh.id);
+            let spec = vehicle_type
+                .and_then(|vt| vt.warehouse_departures.get(&wh.id.to_string()).or(vt.departure.as_ref()))
+                .or(wh.departure.as_ref());
+            let window = match spec {
+                Some(spec) => match spec.at {
+                    Some(at) => DepartureWindow { earliest: at, latest: Some(at) },
+                    None => DepartureWindow { earliest: spec.earliest.unwrap_or(departure), latest: spec.latest },
+                },
+                None => DepartureWindow { earliest: departure, latest: None },
+            };
+            departure_windows.insert(node_id.clone(), window);
             let idx = graph.add_node(node_id.clone());
             node_indices.insert(node_id.clone(), idx);
             warehouse_map.insert(node_id, wh);
@@ -64,112 +137,54 @@ impl PricingProblem {
             customer_map.insert(node_id, cust);
         }
 
+        let locations = warehouse_map.iter()
+            .map(|(id, wh)| Location::from_warehouse(id, wh))
+            .chain(customer_map.iter().map(|(id, cust)| Location::from_customer(id, cust)))
+            .collect();
+        let trip_model = TripCostModel::new(
+            locations,
+            cost_per_km,
+            travel.clone(),
+            // The calculator counts whole minutes
+            (service_duration.num_seconds() as f64 / 60.0).round() as i64,
+            max_capacity,
+            max_stops,
+            penalties.clone(),
+        );
+        let evaluator = build_evaluator(evaluator, route_cache, trip_model);
+
         // Build edges
         let mut pricing = Self {
             graph,
             node_indices,
             customers: customer_map,
             warehouses: warehouse_map,
+            dual_values: dual_values.clone(),
             max_stops,
             max_capacity,
             cost_per_km,
-            speed_kmh,
-            service_time,
-            planning_date,
-            departure_hour,  
+            travel,
+            matrix,
+            distance,
+            service_duration,
+            departure,
+            departure_windows,
+            departure_mode,
+            zone,
             allow_violate_time_window,
-            penalties
+            penalties,
+            exact_sequencing_max_stops,
+            fixed_vehicle_cost,
+            evaluator,
+            evaluator_fallback,
+            consistency_tolerance,
         };
 
-        pricing.build_edges(dual_values);
-        pricing
-    }
-
-    fn calculate_with_executable(&self, path: &[String], departure: DateTime<Utc>) -> Result<f64, String> {
-            // Prepare input
-            let input = serde_json::json!({
-                "locations": self.all_locations(),
-                "path": path,
-                "departure": departure.to_rfc3339(),
-                "cost_per_km": self.cost_per_km,
-                "speed_kmh": self.speed_kmh,
-                "service_minutes": self.service_time,
-                "max_capacity": self.max_capacity,
-                "max_stops": self.max_stops,
-                "allow_violate_time_window": false, // Default to false for safety
-                "penalties": self.penalties  // Pass through penalties
-            });
-            println!("Input for calculator: {}", input);
-
-            // Create temp file
-            let mut file = NamedTempFile::new().map_err(|e| e.to_string())?;
-            file.write_all(input.to_string().as_bytes()).map_err(|e| e.to_string())?;
-
-            // Execute with proper --input flag
-            let output = Command::new("logisticoptimizerv2apppairingsbackend/pairing_api/optimization/rust_trip_calculator")
-                .arg("--input")
-                .arg(file.path())
-                .output()
-                .map_err(|e| e.to_string())?;
-
-            if !output.status.success() {
-                return Err(format!(
-                    "Calculator error ({}): {}",
-                    output.status,
-                    String::from_utf8_lossy(&output.stderr)
-                ));
-            }
-
-            // Parse output
-            let result: serde_json::Value = serde_json::from_slice(&output.stdout)
-                .map_err(|e| e.to_string())?;
-
-            result["total_cost"].as_f64()
-                .ok_or("Missing total_cost in calculator output".to_string())
-        }
-
-    fn all_locations(&self) -> Vec<serde_json::Value> {
-        let mut locations = Vec::new();
-        
-        // Add warehouses
-        for (id, wh) in &self.warehouses {
-            locations.push(serde_json::json!({
-                "id": id,
-                "lat": wh.lat,
-                "lng": wh.lng,
-            }));
-        }
-        
-        // Add customers
-        for (id, cust) in &self.customers {
-            locations.push(serde_json::json!({
-                "id": id,
-                "lat": cust.lat,
-                "lng": cust.lng,
-                "window_start": cust.window_start,
-                "window_end": cust.window_end,
-                "capacity": cust.capacity,
-            }));
-        }
-        
-        locations
-    }
-
-    fn haversine_distance(&self, p1: (f64, f64), p2: (f64, f64)) -> f64 {
-        let (lat1, lon1) = (p1.0.to_radians(), p1.1.to_radians());
-        let (lat2, lon2) = (p2.0.to_radians(), p2.1.to_radians());
-        
-        let dlat = lat2 - lat1;
-        let dlon = lon2 - lon1;
-        
-        let a = (dlat/2.0).sin().powi(2) + 
-                lat1.cos() * lat2.cos() * (dlon/2.0).sin().powi(2);
-        let c = 2.0 * a.sqrt().atan2((1.0-a).sqrt());
-        
-        EARTH_RADIUS_KM * c
+        pricing.build_edges();
+        Ok(pricing)
     }
 
-    fn build_edges(&mut self, dual_values: &HashMap<String, f64>) {
+    fn build_edges(&mut self) {
         // Collect keys first to avoid borrowing issues
         let warehouse_nodes: Vec<String> = self.warehouses.keys().cloned().collect();
         let customer_nodes: Vec<String> = self.customers.keys().cloned().collect();
@@ -177,8 +192,8 @@ impl PricingProblem {
         // Add warehouse<->customer edges
         for wh_node in &warehouse_nodes {
             for cust_node in &customer_nodes {
-                self.add_edge(wh_node, cust_node, dual_values);
-                self.add_edge(cust_node, wh_node, dual_values);
+                self.add_edge(wh_node, cust_node);
+                self.add_edge(cust_node, wh_node);
             }
         }
 
@@ -186,26 +201,32 @@ impl PricingProblem {
         for cust1 in &customer_nodes {
             for cust2 in &customer_nodes {
                 if cust1 != cust2 {
-                    self.add_edge(cust1, cust2, dual_values);
+                    self.add_edge(cust1, cust2);
                 }
             }
         }
     }
 
-    fn add_edge(&mut self, u: &str, v: &str, dual_values: &HashMap<String, f64>) {
+    fn add_edge(&mut self, u: &str, v: &str) {
         let coords_u = self.get_coords(u);
         let coords_v = self.get_coords(v);
         
-        let distance_km = self.haversine_distance(coords_u, coords_v);
+        let class = match (u.starts_with("W_"), v.starts_with("W_")) {
+            (true, _) => ArcClass::FromWarehouse,
+            (false, true) => ArcClass::ToWarehouse,
+            (false, false) => ArcClass::BetweenCustomers,
+        };
+        let distance_km = self.matrix.distance_km(u, v)
+            .unwrap_or_else(|| self.distance.road_km(coords_u, coords_v, class));
         let cost = self.cost_per_km * distance_km;
-        let travel_time = TimeDelta::minutes((60.0 * distance_km / self.speed_kmh) as i64);
-        
-        let reduced_cost = if v.starts_with("C_") {
-            let cust_id = v.split('_').nth(1).unwrap(); // Extract "20" from "C_20"
-            cost - dual_values.get(cust_id).unwrap_or(&0.0)
-        } else {
-            cost
+        let duration_secs = self.matrix.duration_secs(u, v);
+        let travel_time = match duration_secs {
+            Some(secs) => self.travel.truncate(secs),
+            None => self.travel.constant_time(distance_km),
         };
+        let speed_zone = self.travel.speed_zone(coords_u.0, coords_u.1);
+        
+        let reduced_cost = cost - self.dual(v);
 
         let u_idx = self.node_indices[u];
         let v_idx = self.node_indices[v];
@@ -214,13 +235,130 @@ impl PricingProblem {
             u_idx,
             v_idx,
             EdgeData {
+                distance_km,
                 cost,
                 travel_time,
                 reduced_cost,
+                speed_zone,
+                fixed_travel_time: duration_secs.is_some(),
             },
         );
     }
 
+    pub(crate) fn edge(&self, u: &str, v: &str) -> Option<&EdgeData> {
+        let u_idx = *self.node_indices.get(u)?;
+        let v_idx = *self.node_indices.get(v)?;
+        self.graph.edges_connecting(u_idx, v_idx)
+            .next()
+            .map(|e| e.weight())
+    }
+
+    pub(crate) fn customer(&self, node: &str) -> Option<&Customer> {
+        self.customers.get(node)
+    }
+
+    pub(crate) fn customer_ids(&self) -> impl Iterator<Item = &str> {
+        self.customers.keys().map(String::as_str)
+    }
+
+    pub(crate) fn warehouse_ids(&self) -> impl Iterator<Item = &str> {
+        self.warehouses.keys().map(String::as_str)
+    }
+
+    pub(crate) fn warehouse(&self, node: &str) -> Option<&Warehouse> {
+        self.warehouses.get(node)
+    }
+
+    /// Dual value of a customer node, zero for warehouses and customers without one.
+    pub(crate) fn dual(&self, node: &str) -> f64 {
+        node.strip_prefix("C_")
+            .and_then(|id| self.dual_values.get(id))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub(crate) fn fixed_vehicle_cost(&self) -> f64 {
+        self.fixed_vehicle_cost
+    }
+
+    pub(crate) fn cost_per_km(&self) -> f64 {
+        self.cost_per_km
+    }
+
+    pub(crate) fn travel(&self) -> &TravelModel {
+        &self.travel
+    }
+
+    /// When a vehicle that leaves along `edge` at `depart` gets to its end.
+    pub(crate) fn arrival(&self, edge: &EdgeData, depart: DateTime<Utc>) -> DateTime<Utc> {
+        if edge.fixed_travel_time {
+            return depart + edge.travel_time;
+        }
+        self.travel.arrival(edge.distance_km, edge.speed_zone, depart)
+    }
+
+    /// Untruncated seconds from `u` to `v` along `edge` when leaving at
+    /// `depart`: the matrix duration if the arc has one, else the travel model's.
+    pub(crate) fn exact_travel_secs(&self, u: &str, v: &str, edge: &EdgeData, depart: DateTime<Utc>) -> f64 {
+        match self.matrix.duration_secs(u, v) {
+            Some(secs) if edge.fixed_travel_time => secs,
+            _ => self.travel.exact_seconds(edge.distance_km, edge.speed_zone, depart),
+        }
+    }
+
+    pub(crate) fn max_stops(&self) -> usize {
+        self.max_stops
+    }
+
+    pub(crate) fn max_capacity(&self) -> f64 {
+        self.max_capacity
+    }
+
+    pub(crate) fn service_duration(&self) -> TimeDelta {
+        self.service_duration
+    }
+
+    pub(crate) fn penalties(&self) -> &PenaltyParams {
+        &self.penalties
+    }
+
+    pub(crate) fn allow_violate_time_window(&self) -> bool {
+        self.allow_violate_time_window
+    }
+
+    /// `at` as planning-zone local time, for output.
+    pub(crate) fn local_time(&self, at: DateTime<Utc>) -> DateTime<FixedOffset> {
+        self.zone.local(at)
+    }
+
+    pub(crate) fn evaluator(&self) -> &dyn TripCostEvaluator {
+        self.evaluator.as_ref()
+    }
+
+    /// When vehicles may leave `warehouse`. Nodes that are not a warehouse
+    /// get `departure_hour` on the planning date.
+    pub(crate) fn departure_window(&self, warehouse: &str) -> DepartureWindow {
+        self.departure_windows.get(warehouse).copied()
+            .unwrap_or(DepartureWindow { earliest: self.departure, latest: None })
+    }
+
+    /// Earliest departure from `warehouse`.
+    pub(crate) fn departure_from(&self, warehouse: &str) -> DateTime<Utc> {
+        self.departure_window(warehouse).earliest
+    }
+
+    pub(crate) fn departure_mode(&self) -> DepartureMode {
+        self.departure_mode
+    }
+
+    pub(crate) fn distance_metric(&self) -> DistanceMetric {
+        self.distance.metric()
+    }
+
+    pub(crate) fn exact_sequencing_max_stops(&self) -> usize {
+        self.exact_sequencing_max_stops
+    }
+
     fn get_coords(&self, node: &str) -> (f64, f64) {
         if node.starts_with("W_") {
             let wh = &self.warehouses[node];
@@ -231,23 +369,31 @@ impl PricingProblem {
         }
     }
 
-    pub fn find_negative_path(&self) -> Option<PathResult> {
-        let mut best_path = None;
+    /// Best route with negative reduced cost, if there is one. Fails if the
+    /// labeling runs past `time_limit` or no improving route could be costed.
+    pub fn find_negative_path(&self, time_limit: Option<Duration>) -> Result<Option<PathResult>, PricingError> {
+        let started = Instant::now();
+        // Improving routes in the order they were found, so the last is the best
+        let mut candidates: Vec<Candidate> = Vec::new();
         let mut best_reduced_cost = 0.0;
 
         for start_wh in self.warehouses.keys() {
             let start_idx = self.node_indices[start_wh];
-            let departure_time = DateTime::parse_from_rfc3339(
-            format!("{}T{:02}:00:00+06:00", self.planning_date, self.departure_hour).as_str()
-            ).expect("Invalid planning date format").with_timezone(&Utc);
+            let departure_time = self.departure_from(start_wh);
 
-            let mut labels: HashMap<NodeIndex, Vec<(f64, DateTime<Utc>, f64, Vec<String>)>> = HashMap::new();
+            let mut labels: HashMap<NodeIndex, Vec<Label>> = HashMap::new();
             labels.insert(start_idx, vec![(0.0, departure_time, 0.0, vec![start_wh.clone()])]);
             
             let mut queue = VecDeque::new();
             queue.push_back((0.0, departure_time, 0.0, vec![start_wh.clone()]));
 
             while let Some((current_cost, current_time, current_cap, current_path)) = queue.pop_front() {
+                if let Some(limit) = time_limit.filter(|limit| started.elapsed() > *limit) {
+                    return Err(PricingError::Limit(format!(
+                        "Labeling exceeded the time limit of {:?} with {} improving route(s) found",
+                        limit, candidates.len()
+                    )));
+                }
                 let last_node = current_path.last().unwrap();
                 let last_idx = self.node_indices[last_node];
 
@@ -275,7 +421,7 @@ impl PricingProblem {
                     }
 
                     // Calculate new time and capacity
-                    let mut arrival_time = current_time + edge_data.travel_time;
+                    let mut arrival_time = self.arrival(edge_data, current_time);
                     let new_cap = if next_node.starts_with("C_") {
                         let cust = &self.customers[next_node];
                         arrival_time = arrival_time.max(cust.window_start);
@@ -283,7 +429,7 @@ impl PricingProblem {
                             continue;
                         }
                         
-                        let service_end = arrival_time + TimeDelta::minutes(self.service_time);
+                        let service_end = arrival_time + self.service_duration;
                         if service_end > cust.window_end {
                             continue;
                         }
@@ -308,25 +454,31 @@ impl PricingProblem {
                     if next_node == start_wh && customer_count >= 1 {
                         if new_cost < best_reduced_cost {
                             // Found candidate path - now optimize its ordering
-                            let optimized_path = if self.allow_violate_time_window {
-                                self.optimize_path_order(&new_path)
-                            } else {
-                                new_path.clone() // Skip optimization if we can't violate windows
-                            };
-                            
-                            // Calculate exact cost for optimized path
-                            match self.calculate_with_executable(&optimized_path, departure_time) {
-                                Ok(total_cost) => {
-                                    best_reduced_cost = new_cost;
-                                    best_path = Some(PathResult {
-                                        path: optimized_path,
-                                        reduced_cost: new_cost,
-                                        cost: total_cost,
-                                        capacity: new_cap,
-                                    });
-                                },
-                                Err(e) => eprintln!("Calculator error: {}", e),
+                            let mut optimized_path = self.sequence_route(&new_path, departure_time);
+                            let mut departure = self.choose_departure(&optimized_path);
+                            let mut evaluation = self.evaluate_route(&optimized_path, departure);
+
+                            // Reordering must not break constraints the labeling respected
+                            if !evaluation.is_feasible() && !self.allow_violate_time_window {
+                                optimized_path = new_path.clone();
+                                departure = self.choose_departure(&optimized_path);
+                                evaluation = self.evaluate_route(&optimized_path, departure);
+                                if !evaluation.is_feasible() {
+                                    continue;
+                                }
+                            }
+
+                            if evaluation.reduced_cost >= best_reduced_cost {
+                                continue;
                             }
+                            
+                            best_reduced_cost = evaluation.reduced_cost;
+                            candidates.push(Candidate {
+                                labeling_path: new_path,
+                                path: optimized_path,
+                                departure,
+                                evaluation,
+                            });
                         }
                         continue;
                     }
@@ -342,69 +494,71 @@ impl PricingProblem {
             }
         }
 
-        best_path
+        self.price_candidates(candidates)
     }
 
-    fn optimize_path_order(&self, path: &[String]) -> Vec<String> {
-        if path.len() <= 2 {
-            return path.to_vec();
-        }
-
-        // Extract just the customer nodes (excluding start/end warehouses)
-        let mut customers: Vec<_> = path[1..path.len()-1].to_vec();
-        let start_wh = path[0].clone();
-        let end_wh = path.last().unwrap().clone();
-
-        // Use 2-opt algorithm for better path optimization
-        let mut best_path = customers.clone();
-        let mut improved = true;
-        
-        while improved {
-            improved = false;
-            for i in 0..best_path.len() - 1 {
-                for j in i + 1..best_path.len() {
-                    let mut new_path = best_path.clone();
-                    new_path[i..=j].reverse(); // Perform 2-opt swap
-                    
-                    // Calculate total distance for both paths
-                    let current_dist = self.calculate_path_distance(&start_wh, &best_path, &end_wh);
-                    let new_dist = self.calculate_path_distance(&start_wh, &new_path, &end_wh);
-                    
-                    if new_dist < current_dist {
-                        best_path = new_path;
-                        improved = true;
-                    }
-                }
+    /// Costs every candidate in one evaluator batch and returns the best one
+    /// that got a cost, from the evaluator or the fallback.
+    fn price_candidates(&self, candidates: Vec<Candidate>) -> Result<Option<PathResult>, PricingError> {
+        let routes: Vec<_> = candidates.iter()
+            .map(|candidate| (candidate.path.clone(), candidate.departure))
+            .collect();
+        let trips = self.evaluator.evaluate_batch(&routes);
+
+        let mut stats = SearchStats {
+            evaluated_routes: routes.len(),
+            cache: self.evaluator.cache_stats(),
+            ..SearchStats::default()
+        };
+        for e in trips.iter().filter_map(|trip| trip.as_ref().err()) {
+            stats.evaluator_failures += 1;
+            let message = e.to_string();
+            if !stats.evaluator_errors.contains(&message) {
+                stats.evaluator_errors.push(message);
             }
         }
+        let last_error = trips.iter().rev().find_map(|trip| trip.as_ref().err());
 
-        // Reconstruct full path
-        let mut optimized = vec![start_wh];
-        optimized.extend(best_path);
-        optimized.push(end_wh);
-        optimized
-    }
+        let mut best_path = None;
+        for (candidate, trip) in candidates.into_iter().zip(&trips).rev() {
+            let Candidate { labeling_path, path, departure, mut evaluation } = candidate;
+            let (cost, cost_source) = match trip {
+                Ok(trip) => (trip.total_cost, CostSource::Evaluator),
+                Err(_) => match self.evaluator_fallback {
+                    EvaluatorFallback::Skip => continue,
+                    EvaluatorFallback::EdgeCost => (evaluation.breakdown.distance_cost, CostSource::EdgeCost),
+                },
+            };
+            let consistency = self.consistency_tolerance.zip(trip.as_ref().ok()).map(|(tolerance, trip)| {
+                self.consistency_report(&labeling_path, &path, departure, &evaluation.breakdown, trip, tolerance)
+            });
+            evaluation.breakdown.evaluated = Some(self.evaluated_cost(&path, &evaluation.breakdown, trip.as_ref().ok()));
 
-    fn calculate_path_distance(&self, start: &str, customers: &[String], end: &str) -> f64 {
-        let mut distance = 0.0;
-        let mut prev_node = start;
-        
-        for node in customers {
-            distance += self.get_edge_distance(prev_node, node);
-            prev_node = node;
+            best_path = Some(PathResult {
+                path,
+                departure: self.local_time(departure),
+                reduced_cost: evaluation.reduced_cost,
+                cost,
+                capacity: evaluation.load,
+                feasible: evaluation.is_feasible(),
+                violations: evaluation.violations,
+                schedule: evaluation.schedule,
+                cost_breakdown: evaluation.breakdown,
+                cost_source,
+                distance_metric: self.distance_metric(),
+                stats: stats.clone(),
+                consistency,
+            });
+            break;
         }
-        
-        distance += self.get_edge_distance(prev_node, end);
-        distance
-    }
 
-    fn get_edge_distance(&self, u: &str, v: &str) -> f64 {
-        let u_idx = self.node_indices[u];
-        let v_idx = self.node_indices[v];
-        self.graph.edges_connecting(u_idx, v_idx)
-            .next()
-            .map(|e| e.weight().cost / self.cost_per_km) // Convert cost back to distance
-            .unwrap_or(f64::INFINITY)
+        match last_error {
+            Some(e) if best_path.is_none() => Err(PricingError::Evaluator(format!(
+                "no route could be costed, {} of {} failed; last error: {}",
+                stats.evaluator_failures, stats.evaluated_routes, e
+            ))),
+            _ => Ok(best_path),
+        }
     }
 
     fn is_dominated(
@@ -413,27 +567,227 @@ impl PricingProblem {
         cost: f64,
         time: DateTime<Utc>,
         capacity: f64,
-        labels: &HashMap<NodeIndex, Vec<(f64, DateTime<Utc>, f64, Vec<String>)>>,
+        labels: &HashMap<NodeIndex, Vec<Label>>,
     ) -> bool {
-        labels.get(&node).map_or(false, |existing_labels| {
+        labels.get(&node).is_some_and(|existing_labels| {
             existing_labels.iter().any(|(ec, et, ecap, _)| {
                 *ec <= cost && *et <= time && *ecap <= capacity
             })
         })
     }
 
-    fn calculate_path_cost(&self, path: &[String]) -> f64 {
+    /// Edge cost of a path; pairs without an arc, e.g. unknown nodes, add nothing.
+    pub(crate) fn calculate_path_cost(&self, path: &[String]) -> f64 {
         path.windows(2)
-            .map(|pair| {
-                let u = &pair[0];
-                let v = &pair[1];
-                let u_idx = self.node_indices[u];
-                let v_idx = self.node_indices[v];
-                self.graph.edges_connecting(u_idx, v_idx)
-                    .next()
-                    .map(|e| e.weight().cost)
-                    .unwrap_or(0.0)
-            })
+            .filter_map(|pair| self.edge(&pair[0], &pair[1]))
+            .map(|edge| edge.cost)
             .sum()
     }
-}
\ No newline at end of file
+}
+
+/// Great-circle distance in km between two (lat, lng) points.
+pub(crate) fn haversine_distance(p1: (f64, f64), p2: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (p1.0.to_radians(), p1.1.to_radians());
+    let (lat2, lon2) = (p2.0.to_radians(), p2.1.to_radians());
+    
+    let dlat = lat2 - lat1;
+    let dlon = lon2 - lon1;
+    
+    let a = (dlat/2.0).sin().powi(2) + 
+            lat1.cos() * lat2.cos() * (dlon/2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().atan2((1.0-a).sqrt());
+    
+    EARTH_RADIUS_KM * c
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use crate::error::PricingError;
+    use crate::models::{CostSource, DepartureSpec, EvaluatorConfig, IssueKind, MatrixInput, SpeedPeriod, SpeedProfile, TravelMatrix, VehicleType};
+    use crate::{build_pricing, test_input};
+
+    #[test]
+    fn find_negative_path_costs_routes_with_the_mock() {
+        let mut input = test_input();
+        input.customers.truncate(6);
+        input.evaluator = EvaluatorConfig::Mock { fixed_cost: 10.0, cost_per_stop: 2.5 };
+        let pricing = build_pricing(input).unwrap();
+
+        let result = pricing.find_negative_path(None).unwrap().expect("an improving route");
+
+        let stops = result.path.iter().filter(|node| node.starts_with("C_")).count();
+        assert!(stops > 0);
+        assert_eq!(result.cost_source, CostSource::Evaluator);
+        assert_eq!(result.cost, 10.0 + 2.5 * stops as f64);
+        assert!(result.reduced_cost < 0.0);
+        assert_eq!(result.stats.evaluator_failures, 0);
+    }
+
+    #[test]
+    fn returned_route_is_evaluated_in_its_final_order() {
+        let mut input = test_input();
+        input.customers.truncate(6);
+        let pricing = build_pricing(input).unwrap();
+
+        let result = pricing.find_negative_path(None).unwrap().expect("an improving route");
+        let evaluation = pricing.evaluate_route(&result.path, result.departure.with_timezone(&chrono::Utc));
+
+        assert!(result.feasible);
+        assert_eq!(result.reduced_cost, evaluation.reduced_cost);
+        assert_eq!(result.capacity, evaluation.load);
+        assert_eq!(result.schedule.len(), result.path.len());
+        assert_eq!(result.schedule.last().unwrap().arrival, evaluation.schedule.last().unwrap().arrival);
+    }
+
+    fn at(text: &str) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&chrono::Utc)
+    }
+
+    #[test]
+    fn warehouses_leave_at_their_own_release_times() {
+        let mut input = test_input();
+        input.warehouses[1].departure = Some(DepartureSpec { at: Some(at("2025-05-17T07:30:00+06:00")), ..Default::default() });
+        input.warehouses[2].departure = Some(DepartureSpec {
+            latest: Some(at("2025-05-17T09:00:00+06:00")),
+            ..Default::default()
+        });
+        let pricing = build_pricing(input).unwrap();
+
+        let planning = at("2025-05-17T08:00:00+06:00");
+        assert_eq!(pricing.departure_from("W_1"), planning);
+        assert_eq!(pricing.departure_window("W_1").latest, None);
+        assert_eq!(pricing.departure_from("W_2"), at("2025-05-17T07:30:00+06:00"));
+        assert_eq!(pricing.departure_window("W_2").latest, Some(at("2025-05-17T07:30:00+06:00")));
+        // An open earliest is the planning departure
+        assert_eq!(pricing.departure_window("W_3").earliest, planning);
+        assert_eq!(pricing.departure_window("W_3").latest, Some(at("2025-05-17T09:00:00+06:00")));
+
+        let path: Vec<String> = ["W_2", "C_1", "W_2"].iter().map(|id| id.to_string()).collect();
+        let report = pricing.evaluate_paths(&[path]).unwrap().remove(0);
+        assert_eq!(report.schedule[0].departure, at("2025-05-17T07:30:00+06:00"));
+    }
+
+    #[test]
+    fn vehicle_type_departures_take_precedence() {
+        let spec = |text| Some(DepartureSpec { at: Some(at(text)), ..Default::default() });
+        let mut input = test_input();
+        input.warehouses[0].departure = spec("2025-05-17T07:00:00+06:00");
+        input.warehouses[1].departure = spec("2025-05-17T07:00:00+06:00");
+        input.vehicle_types = vec![VehicleType {
+            id: "van".to_string(),
+            departure: spec("2025-05-17T09:00:00+06:00"),
+            warehouse_departures: [("2".to_string(), spec("2025-05-17T10:00:00+06:00").unwrap())].into(),
+        }];
+
+        // Unselected vehicle types change nothing
+        let pricing = build_pricing(input.clone()).unwrap();
+        assert_eq!(pricing.departure_from("W_1"), at("2025-05-17T07:00:00+06:00"));
+
+        input.vehicle_type = Some("van".to_string());
+        let pricing = build_pricing(input).unwrap();
+        assert_eq!(pricing.departure_from("W_1"), at("2025-05-17T09:00:00+06:00"));
+        assert_eq!(pricing.departure_from("W_2"), at("2025-05-17T10:00:00+06:00"));
+        assert_eq!(pricing.departure_from("W_3"), at("2025-05-17T09:00:00+06:00"));
+    }
+
+    #[test]
+    fn short_hops_keep_their_seconds_at_a_finer_resolution() {
+        let hop = |resolution_secs| {
+            let mut input = test_input();
+            // C_2 half a kilometre north of C_1
+            input.customers[1].lat = input.customers[0].lat + 0.0045;
+            input.customers[1].lng = input.customers[0].lng;
+            input.time_resolution_secs = resolution_secs;
+            build_pricing(input).unwrap().edge("C_1", "C_2").unwrap().travel_time
+        };
+
+        assert_eq!(hop(60), TimeDelta::zero());
+        let fine = hop(1);
+        assert!(fine > TimeDelta::zero() && fine < TimeDelta::minutes(1), "{}", fine);
+        assert_eq!(hop(10).num_seconds(), fine.num_seconds() - fine.num_seconds() % 10);
+    }
+
+    #[test]
+    fn service_time_secs_overrides_minutes() {
+        let mut input = test_input();
+        assert_eq!(input.time_resolution_secs, 60, "minute inputs keep whole minutes");
+        assert_eq!(build_pricing(input.clone()).unwrap().service_duration(), TimeDelta::minutes(15));
+
+        input.service_time_secs = Some(90);
+        let pricing = build_pricing(input).unwrap();
+        assert_eq!(pricing.service_duration(), TimeDelta::seconds(90));
+
+        let path: Vec<String> = ["W_1", "C_1", "W_1"].iter().map(|id| id.to_string()).collect();
+        let evaluation = pricing.evaluate_route(&path, pricing.departure_from("W_1"));
+        let stop = &evaluation.schedule[1];
+        assert_eq!(stop.service_end - stop.service_start, TimeDelta::seconds(90));
+    }
+
+    fn matrix(entries: &[(&str, &str, f64, Option<f64>)]) -> MatrixInput {
+        let mut matrix = TravelMatrix::default();
+        for &(from, to, km, secs) in entries {
+            matrix.distances_km.entry(from.to_string()).or_default().insert(to.to_string(), km);
+            if let Some(secs) = secs {
+                matrix.durations_secs.entry(from.to_string()).or_default().insert(to.to_string(), secs);
+            }
+        }
+        MatrixInput { matrix, ..Default::default() }
+    }
+
+    #[test]
+    fn matrix_arcs_win_and_missing_ones_fall_back_to_straight_lines() {
+        let plain = build_pricing(test_input()).unwrap();
+        let mut input = test_input();
+        input.matrix = Some(matrix(&[("W_1", "C_1", 12.0, Some(900.0)), ("C_1", "C_2", 3.0, None)]));
+        let pricing = build_pricing(input).unwrap();
+
+        let out = pricing.edge("W_1", "C_1").unwrap();
+        assert_eq!(out.distance_km, 12.0);
+        assert_eq!(out.cost, 12.0 * 10.0);
+        assert_eq!(out.travel_time, TimeDelta::minutes(15));
+        assert!(out.fixed_travel_time);
+
+        // Only a distance: the duration comes from the speed
+        let hop = pricing.edge("C_1", "C_2").unwrap();
+        assert_eq!(hop.distance_km, 3.0);
+        assert_eq!(hop.travel_time, pricing.travel().constant_time(3.0));
+        assert!(!hop.fixed_travel_time);
+
+        // Matrices need not be symmetric; the way back has no entry
+        let back = pricing.edge("C_1", "W_1").unwrap();
+        assert_eq!(back.distance_km, plain.edge("C_1", "W_1").unwrap().distance_km);
+        assert_eq!(back.travel_time, plain.edge("C_1", "W_1").unwrap().travel_time);
+    }
+
+    #[test]
+    fn matrix_durations_hold_under_a_speed_profile() {
+        let mut input = test_input();
+        input.matrix = Some(matrix(&[("W_1", "C_1", 12.0, Some(900.0))]));
+        input.speed_profile = Some(SpeedProfile {
+            periods: vec![SpeedPeriod { start: chrono::NaiveTime::MIN, speed_kmh: 5.0 }],
+            zones: Vec::new(),
+        });
+        let pricing = build_pricing(input).unwrap();
+
+        let edge = pricing.edge("W_1", "C_1").unwrap();
+        let departure = pricing.departure_from("W_1");
+        assert_eq!(pricing.arrival(edge, departure), departure + TimeDelta::minutes(15));
+        assert_eq!(pricing.exact_travel_secs("W_1", "C_1", edge, departure), 900.0);
+    }
+
+    #[test]
+    fn complete_matrix_is_required_when_asked_for() {
+        let mut input = test_input();
+        let mut partial = matrix(&[("W_1", "C_1", 12.0, Some(900.0))]);
+        partial.require_complete = Some(true);
+        input.matrix = Some(partial);
+
+        let Err(PricingError::Validation(report)) = build_pricing(input) else {
+            panic!("an incomplete matrix is an error");
+        };
+        assert!(report.issues.iter().all(|issue| issue.kind == IssueKind::Missing));
+        assert!(report.issues.iter().any(|issue| issue.field == "matrix.C_1.W_1"));
+        assert!(!report.issues.iter().any(|issue| issue.field == "matrix.W_1.C_1"));
+    }
+}
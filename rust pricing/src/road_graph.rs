@@ -0,0 +1,265 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use crate::error::PricingError;
+use crate::matrix::parse_error;
+use crate::models::{InputData, IssueKind, RoadGraphInput, TravelMatrix, ValidationIssue, ValidationReport};
+use crate::pricing::haversine_distance;
+
+#[derive(Deserialize)]
+struct RoadEdge {
+    from: String,
+    to: String,
+    from_lat: f64,
+    from_lng: f64,
+    to_lat: f64,
+    to_lng: f64,
+    length_m: f64,
+    #[serde(default)]
+    speed_class: String,
+    #[serde(default)]
+    oneway: bool,
+}
+
+/// Road nodes with their coordinates and outgoing (target, seconds, km) arcs.
+struct RoadGraph {
+    coords: Vec<(f64, f64)>,
+    arcs: Vec<Vec<(usize, f64, f64)>>,
+}
+
+/// Dijkstra queue entry, ordered so the heap pops the fastest first.
+#[derive(PartialEq)]
+struct Reached {
+    secs: f64,
+    node: usize,
+}
+
+impl Eq for Reached {}
+
+impl Ord for Reached {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.secs.total_cmp(&self.secs)
+    }
+}
+
+impl PartialOrd for Reached {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl RoadGraphInput {
+    /// Distances and durations of the fastest road path between every pair
+    /// of warehouses and customers. Each location is snapped to its nearest
+    /// road node. A pair the labeling drives with no road path between it is
+    /// a validation issue, unless `known` (the input matrix) covers it.
+    pub fn matrix(&self, input: &InputData, known: &TravelMatrix) -> Result<TravelMatrix, PricingError> {
+        let graph = self.load(input.speed_kmh)?;
+        let locations: Vec<(String, f64, f64)> = input.warehouses.iter()
+            .map(|wh| (format!("W_{}", wh.id), wh.lat, wh.lng))
+            .chain(input.customers.iter().map(|cust| (format!("C_{}", cust.id), cust.lat, cust.lng)))
+            .collect();
+
+        let mut snapped = Vec::with_capacity(locations.len());
+        let mut issues = Vec::new();
+        for (id, lat, lng) in &locations {
+            match graph.nearest((*lat, *lng)) {
+                Some((node, km)) if km <= self.max_snap_km => snapped.push((node, km)),
+                nearest => issues.push(ValidationIssue {
+                    kind: IssueKind::OutOfRange,
+                    field: "road_graph.max_snap_km".to_string(),
+                    id: Some(id.clone()),
+                    message: match nearest {
+                        Some((_, km)) => format!(
+                            "{} is {:.3} km from the nearest road node, more than {}", id, km, self.max_snap_km
+                        ),
+                        None => "The road graph has no nodes".to_string(),
+                    },
+                }),
+            }
+        }
+        if !issues.is_empty() {
+            return Err(PricingError::Validation(ValidationReport { valid: false, issues }));
+        }
+
+        let access_secs = |km: f64| 3600.0 * km / input.speed_kmh;
+        let mut matrix = TravelMatrix::default();
+        let mut paths: HashMap<usize, Vec<Option<(f64, f64)>>> = HashMap::new();
+        for (i, (from, _, _)) in locations.iter().enumerate() {
+            let (source, source_km) = snapped[i];
+            let reached = paths.entry(source).or_insert_with(|| graph.fastest_from(source));
+            for (j, (to, _, _)) in locations.iter().enumerate() {
+                let (target, target_km) = snapped[j];
+                if i == j {
+                    continue;
+                }
+                let Some((secs, km)) = reached[target] else {
+                    let arc = !(from.starts_with("W_") && to.starts_with("W_"));
+                    if arc && known.distance_km(from, to).is_none() && known.duration_secs(from, to).is_none() {
+                        issues.push(ValidationIssue {
+                            kind: IssueKind::Unreachable,
+                            field: "road_graph.file".to_string(),
+                            id: Some(from.clone()),
+                            message: format!("No road path from {} to {}", from, to),
+                        });
+                    }
+                    continue;
+                };
+                matrix.distances_km.entry(from.clone()).or_default()
+                    .insert(to.clone(), source_km + km + target_km);
+                matrix.durations_secs.entry(from.clone()).or_default()
+                    .insert(to.clone(), access_secs(source_km) + secs + access_secs(target_km));
+            }
+        }
+        if !issues.is_empty() {
+            return Err(PricingError::Validation(ValidationReport { valid: false, issues }));
+        }
+        Ok(matrix)
+    }
+
+    fn load(&self, default_speed_kmh: f64) -> Result<RoadGraph, PricingError> {
+        let text = std::fs::read_to_string(&self.file)
+            .map_err(|e| io::Error::new(e.kind(), format!("Road graph {}: {}", self.file, e)))?;
+
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut graph = RoadGraph { coords: Vec::new(), arcs: Vec::new() };
+        let mut node = |graph: &mut RoadGraph, id: String, coords: (f64, f64)| {
+            *index.entry(id).or_insert_with(|| {
+                graph.coords.push(coords);
+                graph.arcs.push(Vec::new());
+                graph.coords.len() - 1
+            })
+        };
+
+        for edge in csv::Reader::from_reader(text.as_bytes()).deserialize::<RoadEdge>() {
+            let edge = edge.map_err(|e| parse_error(format!("road graph {}: {}", self.file, e)))?;
+            let speed_kmh = self.speed_classes.get(&edge.speed_class).copied().unwrap_or(default_speed_kmh);
+            if !(edge.length_m >= 0.0 && speed_kmh > 0.0) {
+                return Err(parse_error(format!(
+                    "road graph {}: edge {}-{} needs a length of at least 0 and a positive speed",
+                    self.file, edge.from, edge.to
+                )));
+            }
+            let from = node(&mut graph, edge.from, (edge.from_lat, edge.from_lng));
+            let to = node(&mut graph, edge.to, (edge.to_lat, edge.to_lng));
+            let km = edge.length_m / 1000.0;
+            let secs = 3600.0 * km / speed_kmh;
+            graph.arcs[from].push((to, secs, km));
+            if !edge.oneway {
+                graph.arcs[to].push((from, secs, km));
+            }
+        }
+        Ok(graph)
+    }
+}
+
+impl RoadGraph {
+    /// Closest road node to `point` and its straight-line distance in km.
+    fn nearest(&self, point: (f64, f64)) -> Option<(usize, f64)> {
+        self.coords.iter()
+            .map(|coords| haversine_distance(point, *coords))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// (seconds, km) of the fastest path from `source` to every node.
+    fn fastest_from(&self, source: usize) -> Vec<Option<(f64, f64)>> {
+        let mut best: Vec<Option<(f64, f64)>> = vec![None; self.coords.len()];
+        let mut heap = BinaryHeap::new();
+        best[source] = Some((0.0, 0.0));
+        heap.push(Reached { secs: 0.0, node: source });
+
+        while let Some(Reached { secs, node }) = heap.pop() {
+            let Some((node_secs, node_km)) = best[node] else { continue };
+            if secs > node_secs {
+                continue;
+            }
+            for &(next, arc_secs, arc_km) in &self.arcs[node] {
+                let next_secs = node_secs + arc_secs;
+                if best[next].is_none_or(|(known, _)| next_secs < known) {
+                    best[next] = Some((next_secs, node_km + arc_km));
+                    heap.push(Reached { secs: next_secs, node: next });
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0 -> 1 directly is short but slow; 0 -> 2 -> 1 is longer but faster.
+    /// Node 3 can only be left.
+    fn graph() -> RoadGraph {
+        RoadGraph {
+            coords: vec![(43.0, 76.0), (43.0, 76.1), (43.05, 76.05), (43.1, 76.0)],
+            arcs: vec![
+                vec![(1, 600.0, 5.0), (2, 200.0, 4.0)],
+                vec![(0, 600.0, 5.0)],
+                vec![(1, 200.0, 4.0)],
+                vec![(0, 60.0, 1.0)],
+            ],
+        }
+    }
+
+    #[test]
+    fn dijkstra_takes_the_fastest_path_and_reports_its_length() {
+        let reached = graph().fastest_from(0);
+
+        assert_eq!(reached[0], Some((0.0, 0.0)));
+        assert_eq!(reached[1], Some((400.0, 8.0)));
+        assert_eq!(reached[2], Some((200.0, 4.0)));
+        assert_eq!(reached[3], None);
+        assert_eq!(graph().fastest_from(3)[1], Some((460.0, 9.0)));
+    }
+
+    #[test]
+    fn locations_snap_to_the_closest_node() {
+        let (node, km) = graph().nearest((43.049, 76.051)).unwrap();
+
+        assert_eq!(node, 2);
+        assert!(km < 0.2);
+        assert!(RoadGraph { coords: Vec::new(), arcs: Vec::new() }.nearest((43.0, 76.0)).is_none());
+    }
+
+    #[test]
+    fn pairs_without_a_road_path_are_reported_unless_known() {
+        let mut input = crate::test_input();
+        input.warehouses.truncate(1);
+        input.customers.truncate(2);
+        let (w, c1, c2) = (&input.warehouses[0], &input.customers[0], &input.customers[1]);
+        // W_1 and C_1 share a road, C_2's road is not connected to it.
+        let csv = format!(
+            "from,to,from_lat,from_lng,to_lat,to_lng,length_m\na,b,{},{},{},{},5000\nc,d,{},{},{},{},100\n",
+            w.lat, w.lng, c1.lat, c1.lng, c2.lat, c2.lng, c2.lat + 0.001, c2.lng,
+        );
+        let file = std::env::temp_dir().join(format!("vrp_pricing_roads_{}.csv", std::process::id()));
+        std::fs::write(&file, csv).unwrap();
+        let road_graph = RoadGraphInput { file: file.display().to_string(), speed_classes: HashMap::new(), max_snap_km: 1.0 };
+
+        let Err(PricingError::Validation(report)) = road_graph.matrix(&input, &TravelMatrix::default()) else {
+            panic!("unreachable pairs are an error");
+        };
+        let pairs: Vec<&str> = report.issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(pairs, [
+            "No road path from W_1 to C_2",
+            "No road path from C_1 to C_2",
+            "No road path from C_2 to W_1",
+            "No road path from C_2 to C_1",
+        ]);
+        assert!(report.issues.iter().all(|issue| issue.kind == IssueKind::Unreachable));
+
+        let mut known = TravelMatrix::default();
+        for (from, to) in [("W_1", "C_2"), ("C_1", "C_2"), ("C_2", "W_1"), ("C_2", "C_1")] {
+            known.distances_km.entry(from.to_string()).or_default().insert(to.to_string(), 1.0);
+        }
+        let matrix = road_graph.matrix(&input, &known).unwrap();
+        assert_eq!(matrix.distance_km("W_1", "C_1"), Some(5.0));
+        assert_eq!(matrix.distance_km("W_1", "C_2"), None);
+
+        std::fs::remove_file(&file).unwrap();
+    }
+}
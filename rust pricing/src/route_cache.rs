@@ -0,0 +1,295 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::error::EvaluatorError;
+use crate::evaluator::{RouteRequest, TripCostEvaluator};
+use crate::models::{CacheStats, EvaluatorConfig};
+use crate::trip_cost::{TripCost, TripCostModel};
+
+/// Bumped whenever the key or the stored `TripCost` changes shape.
+const CACHE_FILE_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// A cached trip with the text its key hashes, compared on lookup so that
+/// two inputs sharing a hash never share a cost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    input: String,
+    trip: TripCost,
+}
+
+/// Memoizes another evaluator's trip costs.
+///
+/// A key covers everything the cost can depend on: the route, its
+/// departure, the cost parameters and planning timezone, the locations it
+/// visits and the evaluator configuration. Entries stay valid across runs on other
+/// instances, so they can be shared through a cache file. Keys are hashes;
+/// each entry keeps the hashed text and only answers a lookup of the same text.
+pub struct CachedEvaluator {
+    inner: Box<dyn TripCostEvaluator>,
+    model: TripCostModel,
+    backend: String,
+    file: Option<PathBuf>,
+    entries: RefCell<HashMap<String, CacheEntry>>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+    /// Why the cache file could not be read or written, if it could not.
+    file_error: RefCell<Option<String>>,
+}
+
+impl CachedEvaluator {
+    /// Wraps `inner`, loading earlier entries from `file` if it exists. A
+    /// file that cannot be read starts an empty cache.
+    pub fn new(inner: Box<dyn TripCostEvaluator>, model: TripCostModel, config: &EvaluatorConfig, file: Option<PathBuf>) -> Self {
+        let mut file_error = None;
+        let entries = match &file {
+            Some(path) => load(path).unwrap_or_else(|e| {
+                file_error = Some(format!("Could not read {}: {}", path.display(), e));
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+        Self {
+            inner,
+            model,
+            backend: serde_json::to_string(config).unwrap_or_default(),
+            file,
+            entries: RefCell::new(entries),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+            file_error: RefCell::new(file_error),
+        }
+    }
+
+    /// Key of a route and the text it hashes: the evaluator configuration
+    /// followed by the route's calculator input.
+    fn key(&self, path: &[String], departure: DateTime<Utc>) -> (String, String) {
+        let input = format!("{}{}", self.backend, self.model.route_input(path, departure));
+        (format!("{:016x}", fnv1a(input.as_bytes().iter())), input)
+    }
+
+    /// Rewrites the cache file through a temporary file, so concurrent runs
+    /// never see half of it. A cache that cannot be written only costs speed,
+    /// so the error is kept for the cache stats.
+    fn save(&self) {
+        let Some(path) = &self.file else { return };
+        if let Err(e) = self.write(path) {
+            *self.file_error.borrow_mut() = Some(format!("Could not write {}: {}", path.display(), e));
+        }
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let file = CacheFile { version: CACHE_FILE_VERSION, entries: self.entries.borrow().clone() };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&file)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl TripCostEvaluator for CachedEvaluator {
+    fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+        self.evaluate_batch(&[(path.to_vec(), departure)])
+            .pop()
+            .expect("one result per route")
+    }
+
+    /// Only the misses reach the inner evaluator, still as one batch. Failed
+    /// evaluations are not cached.
+    fn evaluate_batch(&self, routes: &[RouteRequest]) -> Vec<Result<TripCost, EvaluatorError>> {
+        let keys: Vec<(String, String)> = routes.iter().map(|(path, departure)| self.key(path, *departure)).collect();
+        let mut results: Vec<Option<Result<TripCost, EvaluatorError>>> = {
+            let entries = self.entries.borrow();
+            keys.iter()
+                .map(|(key, input)| entries.get(key).filter(|entry| &entry.input == input).map(|entry| Ok(entry.trip.clone())))
+                .collect()
+        };
+
+        let missing: Vec<usize> = (0..routes.len()).filter(|&i| results[i].is_none()).collect();
+        self.hits.set(self.hits.get() + routes.len() - missing.len());
+        self.misses.set(self.misses.get() + missing.len());
+
+        if !missing.is_empty() {
+            let requests: Vec<RouteRequest> = missing.iter().map(|&i| routes[i].clone()).collect();
+            let mut stored = false;
+            for (&i, result) in missing.iter().zip(self.inner.evaluate_batch(&requests)) {
+                if let Ok(trip) = &result {
+                    let (key, input) = keys[i].clone();
+                    self.entries.borrow_mut().insert(key, CacheEntry { input, trip: trip.clone() });
+                    stored = true;
+                }
+                results[i] = Some(result);
+            }
+            if stored {
+                self.save();
+            }
+        }
+
+        results.into_iter().map(|result| result.expect("every route is answered")).collect()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(CacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            file_error: self.file_error.borrow().clone(),
+        })
+    }
+}
+
+/// Entries of a cache file; a missing or outdated file is an empty cache.
+fn load(path: &Path) -> io::Result<HashMap<String, CacheEntry>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let file: CacheFile = serde_json::from_slice(&bytes)?;
+    Ok(if file.version == CACHE_FILE_VERSION { file.entries } else { HashMap::new() })
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` it is stable across builds, which
+/// the cache file relies on.
+fn fnv1a<'a>(bytes: impl Iterator<Item = &'a u8>) -> u64 {
+    bytes.fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::rc::Rc;
+    use crate::test_trip_model;
+
+    /// The in-process model, counting the routes it is asked to cost.
+    struct Counting {
+        model: TripCostModel,
+        routes: Rc<Cell<usize>>,
+    }
+
+    impl TripCostEvaluator for Counting {
+        fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+            self.routes.set(self.routes.get() + 1);
+            self.model.evaluate(path, departure)
+        }
+    }
+
+    fn cached(file: Option<PathBuf>) -> (CachedEvaluator, Rc<Cell<usize>>) {
+        let routes = Rc::new(Cell::new(0));
+        let inner = Counting { model: test_trip_model(), routes: routes.clone() };
+        (CachedEvaluator::new(Box::new(inner), test_trip_model(), &EvaluatorConfig::Internal, file), routes)
+    }
+
+    fn route(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn departure() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 5, 17, 2, 0, 0).unwrap()
+    }
+
+    fn cache_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vrp_pricing_{}_{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn repeated_routes_are_served_from_the_cache() {
+        let (cache, routes) = cached(None);
+        let a = route(&["W_1", "C_1", "W_1"]);
+        let b = route(&["W_1", "C_2", "W_1"]);
+
+        let first = cache.evaluate_batch(&[(a.clone(), departure()), (b.clone(), departure())]);
+        assert_eq!(routes.get(), 2);
+        let second = cache.evaluate_batch(&[(b.clone(), departure()), (a.clone(), departure())]);
+        assert_eq!(routes.get(), 2, "both routes were cached");
+        assert_eq!(first[0].as_ref().unwrap().total_cost, second[1].as_ref().unwrap().total_cost);
+        assert_eq!(first[1].as_ref().unwrap().total_cost, second[0].as_ref().unwrap().total_cost);
+
+        // Another departure is another key.
+        cache.evaluate(&a, departure() + chrono::TimeDelta::hours(1)).unwrap();
+        assert_eq!(routes.get(), 3);
+
+        let stats = cache.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (2, 3));
+        assert_eq!(stats.file_error, None);
+    }
+
+    #[test]
+    fn failed_evaluations_are_not_cached() {
+        let (cache, routes) = cached(None);
+        let unknown = route(&["W_1", "C_404", "W_1"]);
+
+        assert!(cache.evaluate(&unknown, departure()).is_err());
+        assert!(cache.evaluate(&unknown, departure()).is_err());
+        assert_eq!(routes.get(), 2);
+        assert_eq!(cache.cache_stats().unwrap().hits, 0);
+    }
+
+    #[test]
+    fn entries_survive_a_save_and_load() {
+        let path = cache_file("round_trip");
+        let a = route(&["W_1", "C_1", "C_2", "W_1"]);
+
+        let (writer, _) = cached(Some(path.clone()));
+        let written = writer.evaluate(&a, departure()).unwrap();
+        assert!(path.exists());
+        assert!(!path.with_extension("tmp").exists(), "the temporary file is renamed into place");
+
+        let (reader, routes) = cached(Some(path.clone()));
+        let read = reader.evaluate(&a, departure()).unwrap();
+        assert_eq!(routes.get(), 0);
+        assert_eq!(read.total_cost, written.total_cost);
+        assert_eq!(read.completion_time, written.completion_time);
+        assert_eq!(reader.cache_stats().unwrap().hits, 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn entry_for_another_input_under_the_same_key_is_a_miss() {
+        let path = cache_file("collision");
+        let a = route(&["W_1", "C_1", "W_1"]);
+        let (writer, _) = cached(Some(path.clone()));
+        writer.evaluate(&a, departure()).unwrap();
+
+        // Pretend another input hashed to the same key.
+        let mut file: CacheFile = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        for entry in file.entries.values_mut() {
+            entry.input.push(' ');
+        }
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+        let (reader, routes) = cached(Some(path.clone()));
+        reader.evaluate(&a, departure()).unwrap();
+        assert_eq!(routes.get(), 1);
+        assert_eq!(reader.cache_stats().unwrap().misses, 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_file_starts_an_empty_cache() {
+        let path = cache_file("corrupt");
+        fs::write(&path, "not json").unwrap();
+
+        let (cache, routes) = cached(Some(path.clone()));
+        cache.evaluate(&route(&["W_1", "C_1", "W_1"]), departure()).unwrap();
+
+        assert_eq!(routes.get(), 1);
+        assert!(cache.cache_stats().unwrap().file_error.unwrap().starts_with("Could not read"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use permutohedron::heap_recursive;
+use std::iter;
+use crate::local_search::RouteData;
+use crate::pricing::PricingProblem;
+use crate::travel_time::minutes;
+
+/// Routes with at most this many customers are resequenced by trying every order.
+const PERMUTATION_MAX_STOPS: usize = 6;
+/// Largest route Held–Karp may take: its states grow as 2^stops * stops.
+pub const HELD_KARP_MAX_STOPS: usize = 15;
+
+/// Held–Karp label: a way of visiting a subset of customers that ends at
+/// a given customer, with its objective and the time the vehicle leaves.
+struct Label {
+    objective: f64,
+    ready: DateTime<Utc>,
+    /// Label it was extended from, as (last customer, index in that state).
+    prev: Option<(usize, usize)>,
+}
+
+impl PricingProblem {
+    /// Resequences the customers of a closed route so the route objective
+    /// (see `improve_route`) is optimal.
+    ///
+    /// Tiny routes try every order, routes up to `exact_sequencing_max_stops`
+    /// customers (at most `HELD_KARP_MAX_STOPS`) run a Held–Karp DP with
+    /// time windows, and longer routes fall back to local search.
+    pub(crate) fn sequence_route(&self, path: &[String], departure: DateTime<Utc>) -> Vec<String> {
+        let stops = path.len().saturating_sub(2);
+        if stops <= 1 {
+            return path.to_vec();
+        }
+        if stops > self.exact_sequencing_max_stops().min(HELD_KARP_MAX_STOPS) {
+            return self.improve_route(path, departure);
+        }
+
+        let data = self.route_data(path, departure);
+        let order = if stops <= PERMUTATION_MAX_STOPS {
+            best_permutation(&data, stops)
+        } else {
+            held_karp(&data, stops)
+        };
+
+        match order {
+            Some(route) => route.into_iter().map(|pos| path[pos].clone()).collect(),
+            None => self.improve_route(path, departure),
+        }
+    }
+}
+
+fn best_permutation(data: &RouteData, stops: usize) -> Option<Vec<usize>> {
+    let end = stops + 1;
+    let mut customers: Vec<usize> = (1..end).collect();
+    let mut best: Option<(f64, Vec<usize>)> = None;
+
+    heap_recursive(&mut customers, |order| {
+        let route: Vec<usize> = iter::once(0)
+            .chain(order.iter().copied())
+            .chain(iter::once(end))
+            .collect();
+        if let Some(objective) = data.objective(&route) {
+            if best.as_ref().is_none_or(|(best_objective, _)| objective < *best_objective) {
+                best = Some((objective, route));
+            }
+        }
+    });
+
+    best.map(|(_, route)| route)
+}
+
+/// Exact DP over (visited subset, last customer) with Pareto sets of
+/// (objective, ready time) labels, since windows make a single best label
+/// per state insufficient.
+fn held_karp(data: &RouteData, stops: usize) -> Option<Vec<usize>> {
+    let end = stops + 1;
+    let full = (1usize << stops) - 1;
+    let waiting_rate = data.waiting_rate();
+    let state = |mask: usize, last: usize| mask * stops + last;
+
+    let mut states: Vec<Vec<Label>> = (0..(full + 1) * stops).map(|_| Vec::new()).collect();
+
+    for first in 0..stops {
+        let visit = data.visit(0, first + 1, data.departure);
+        if !data.allow_violation && !visit.feasible {
+            continue;
+        }
+        let label = Label {
+            objective: data.cost[0][first + 1] + data.visit_penalty(&visit),
+            ready: visit.departure,
+            prev: None,
+        };
+        insert_label(&mut states[state(1 << first, first)], label, waiting_rate);
+    }
+
+    // Every predecessor of a subset is numerically smaller, so by the time a
+    // subset is extended all of its labels are final.
+    for mask in 1..=full {
+        for last in (0..stops).filter(|last| mask & (1 << last) != 0) {
+            for index in 0..states[state(mask, last)].len() {
+                let label = &states[state(mask, last)][index];
+                let (objective, ready) = (label.objective, label.ready);
+
+                for next in (0..stops).filter(|next| mask & (1 << next) == 0) {
+                    let visit = data.visit(last + 1, next + 1, ready);
+                    if !data.allow_violation && !visit.feasible {
+                        continue;
+                    }
+                    let label = Label {
+                        objective: objective + data.cost[last + 1][next + 1] + data.visit_penalty(&visit),
+                        ready: visit.departure,
+                        prev: Some((last, index)),
+                    };
+                    insert_label(&mut states[state(mask | 1 << next, next)], label, waiting_rate);
+                }
+            }
+        }
+    }
+
+    let mut best: Option<(f64, usize, usize)> = None;
+    for last in 0..stops {
+        for (index, label) in states[state(full, last)].iter().enumerate() {
+            let objective = label.objective + data.cost[last + 1][end];
+            if best.is_none_or(|(best_objective, _, _)| objective < best_objective) {
+                best = Some((objective, last, index));
+            }
+        }
+    }
+
+    let (_, mut last, mut index) = best?;
+    let mut mask = full;
+    let mut order = vec![end];
+    loop {
+        order.push(last + 1);
+        match states[state(mask, last)][index].prev {
+            Some((prev_last, prev_index)) => {
+                mask ^= 1 << last;
+                last = prev_last;
+                index = prev_index;
+            }
+            None => break,
+        }
+    }
+    order.push(0);
+    order.reverse();
+    Some(order)
+}
+
+/// Adds a label unless another one dominates it. An earlier label dominates
+/// a later one if it stays cheaper after paying for the extra time spent waiting.
+fn insert_label(labels: &mut Vec<Label>, label: Label, waiting_rate: f64) {
+    let dominates = |a: &Label, b: &Label| {
+        a.ready <= b.ready
+            && a.objective + waiting_rate * minutes(b.ready - a.ready) <= b.objective
+    };
+
+    if labels.iter().any(|existing| dominates(existing, &label)) {
+        return;
+    }
+    labels.retain(|existing| !dominates(&label, existing));
+    labels.push(label);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_pricing, test_input};
+
+    /// Held–Karp finds an order as good as trying every one.
+    #[test]
+    fn held_karp_matches_exhaustive_search() {
+        for allow_violation in [false, true] {
+            let mut input = test_input();
+            input.allow_violate_time_window = allow_violation;
+            let pricing = build_pricing(input).unwrap();
+            let path: Vec<String> = std::iter::once("W_1".to_string())
+                .chain([9, 3, 14, 1, 7, 12, 5].map(|id| format!("C_{}", id)))
+                .chain(std::iter::once("W_1".to_string()))
+                .collect();
+            let data = pricing.route_data(&path, pricing.departure_from("W_1"));
+            let stops = path.len() - 2;
+
+            let exact = held_karp(&data, stops).and_then(|route| data.objective(&route));
+            let exhaustive = best_permutation(&data, stops).and_then(|route| data.objective(&route));
+
+            match (exact, exhaustive) {
+                (Some(exact), Some(exhaustive)) => assert!((exact - exhaustive).abs() < 1e-9, "{} vs {}", exact, exhaustive),
+                (exact, exhaustive) => assert_eq!(exact.is_some(), exhaustive.is_some()),
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeDelta, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// UTC offset used when the input names no timezone.
+const DEFAULT_OFFSET_SECONDS: i32 = 6 * 3600;
+/// Longer than any gap a DST change leaves in local time.
+const MAX_GAP_MINUTES: i64 = 24 * 60;
+
+/// Zone the planning day is expressed in: departures are local wall-clock
+/// hours and output times carry the offset in effect at that instant.
+#[derive(Debug, Clone, Copy)]
+pub enum PlanningZone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Default for PlanningZone {
+    fn default() -> Self {
+        PlanningZone::Fixed(FixedOffset::east_opt(DEFAULT_OFFSET_SECONDS).expect("Default offset in range"))
+    }
+}
+
+impl PlanningZone {
+    /// Zone for an optional IANA name such as `Europe/Berlin`.
+    pub fn from_name(name: Option<&str>) -> Result<Self, String> {
+        match name {
+            None => Ok(PlanningZone::default()),
+            Some(name) => name.parse::<Tz>()
+                .map(PlanningZone::Named)
+                .map_err(|_| format!("Unknown IANA timezone '{}'", name)),
+        }
+    }
+
+    /// IANA name, or the `+06:00` form of a fixed offset.
+    pub fn name(&self) -> String {
+        match self {
+            PlanningZone::Fixed(offset) => offset.to_string(),
+            PlanningZone::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    /// `at` in local time, with the offset in effect at that instant.
+    pub fn local(&self, at: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            PlanningZone::Fixed(offset) => at.with_timezone(offset),
+            PlanningZone::Named(tz) => at.with_timezone(&tz.offset_from_utc_datetime(&at.naive_utc()).fix()),
+        }
+    }
+
+    /// The instant of `hour`:00 local time on `date`. An hour repeated by a
+    /// DST change means its first occurrence; one skipped by it has none.
+    pub fn at_hour(&self, date: NaiveDate, hour: u32) -> Option<DateTime<Utc>> {
+        let naive = date.and_hms_opt(hour, 0, 0)?;
+        let local = match self {
+            PlanningZone::Fixed(offset) => naive.and_local_timezone(*offset).earliest()?.with_timezone(&Utc),
+            PlanningZone::Named(tz) => naive.and_local_timezone(*tz).earliest()?.with_timezone(&Utc),
+        };
+        Some(local)
+    }
+
+    /// The first instant from `not_before` on at which the local time is
+    /// `naive`. A time skipped by a DST change is reached when the clocks
+    /// jump past it.
+    pub fn next_instant(&self, naive: NaiveDateTime, not_before: DateTime<Utc>) -> DateTime<Utc> {
+        let tz = match self {
+            PlanningZone::Fixed(offset) => return (naive - TimeDelta::seconds(offset.local_minus_utc().into())).and_utc(),
+            PlanningZone::Named(tz) => tz,
+        };
+        // Changes happen on whole minutes, so after a gap the first valid minute is where it ends
+        let minute = naive.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(naive);
+        std::iter::once(naive)
+            .chain((1..=MAX_GAP_MINUTES).map(|k| minute + TimeDelta::minutes(k)))
+            .find_map(|probe| {
+                let local = probe.and_local_timezone(*tz);
+                [local.earliest(), local.latest()].into_iter()
+                    .flatten()
+                    .map(|at| at.with_timezone(&Utc))
+                    .find(|at| *at >= not_before)
+            })
+            .unwrap_or(not_before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(at).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn default_is_a_fixed_plus_six_hours() {
+        let zone = PlanningZone::from_name(None).unwrap();
+        let date = NaiveDate::from_ymd_opt(2025, 5, 17).unwrap();
+
+        assert_eq!(zone.at_hour(date, 8), Some(utc("2025-05-17T02:00:00Z")));
+        assert_eq!(zone.local(utc("2025-05-17T02:00:00Z")).to_rfc3339(), "2025-05-17T08:00:00+06:00");
+        assert_eq!(zone.name(), "+06:00");
+        assert!(PlanningZone::from_name(Some("Mars/Olympus_Mons")).is_err());
+    }
+
+    #[test]
+    fn spring_forward_skips_an_hour() {
+        let berlin = PlanningZone::from_name(Some("Europe/Berlin")).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+
+        assert_eq!(berlin.at_hour(date, 1), Some(utc("2024-03-31T00:00:00Z")));
+        assert_eq!(berlin.at_hour(date, 2), None);
+        assert_eq!(berlin.at_hour(date, 3), Some(utc("2024-03-31T01:00:00Z")));
+        assert_eq!(berlin.local(utc("2024-03-31T01:00:00Z")).to_rfc3339(), "2024-03-31T03:00:00+02:00");
+    }
+
+    #[test]
+    fn fall_back_takes_the_first_of_a_repeated_hour() {
+        let berlin = PlanningZone::from_name(Some("Europe/Berlin")).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 10, 27).unwrap();
+
+        assert_eq!(berlin.at_hour(date, 2), Some(utc("2024-10-27T00:00:00Z")));
+        assert_eq!(berlin.at_hour(date, 3), Some(utc("2024-10-27T02:00:00Z")));
+        assert_eq!(berlin.local(utc("2024-10-27T01:30:00Z")).to_rfc3339(), "2024-10-27T02:30:00+01:00");
+    }
+}
@@ -0,0 +1,232 @@
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use crate::models::{SpeedPeriod, SpeedProfile};
+use crate::timezone::PlanningZone;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Travel times of the labeling, the schedule and the in-process evaluator:
+/// a constant `speed_kmh`, or a speed profile evaluated in the planning
+/// timezone.
+#[derive(Debug, Clone)]
+pub struct TravelModel {
+    speed_kmh: f64,
+    resolution_secs: i64,
+    zone: PlanningZone,
+    profile: Option<SpeedProfile>,
+    /// (start second of day, speed) sorted by start; index 0 holds the
+    /// profile's own periods, then one entry per speed zone.
+    periods: Vec<Vec<(f64, f64)>>,
+}
+
+impl TravelModel {
+    pub fn new(speed_kmh: f64, resolution_secs: i64, zone: PlanningZone, profile: Option<SpeedProfile>) -> Self {
+        let periods = profile.iter()
+            .flat_map(|profile| std::iter::once(&profile.periods).chain(profile.zones.iter().map(|z| &z.periods)))
+            .map(|periods| sorted_periods(periods))
+            .collect();
+        Self { speed_kmh, resolution_secs, zone, profile, periods }
+    }
+
+    pub fn speed_kmh(&self) -> f64 {
+        self.speed_kmh
+    }
+
+    pub fn zone(&self) -> PlanningZone {
+        self.zone
+    }
+
+    pub fn profile(&self) -> Option<&SpeedProfile> {
+        self.profile.as_ref()
+    }
+
+    /// Whether travel times depend on the time of departure.
+    pub fn is_time_dependent(&self) -> bool {
+        self.periods.iter().any(|periods| !periods.is_empty())
+    }
+
+    /// Speed zone of a leg starting at (lat, lng); 0 outside every zone.
+    pub fn speed_zone(&self, lat: f64, lng: f64) -> usize {
+        self.profile.iter()
+            .flat_map(|profile| profile.zones.iter())
+            .position(|z| (z.min_lat..=z.max_lat).contains(&lat) && (z.min_lng..=z.max_lng).contains(&lng))
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Travel time at `speed_kmh`, truncated to the time resolution.
+    pub fn constant_time(&self, distance_km: f64) -> TimeDelta {
+        self.truncate(3600.0 * distance_km / self.speed_kmh)
+    }
+
+    /// `secs` truncated to the time resolution.
+    pub fn truncate(&self, secs: f64) -> TimeDelta {
+        let secs = secs as i64;
+        TimeDelta::seconds(secs - secs % self.resolution_secs)
+    }
+
+    /// Untruncated seconds to drive `distance_km` leaving at `depart`.
+    ///
+    /// The distance is covered at each period's speed in turn, so leaving
+    /// later never arrives earlier (FIFO).
+    pub fn exact_seconds(&self, distance_km: f64, speed_zone: usize, depart: DateTime<Utc>) -> f64 {
+        let periods = match self.periods.get(speed_zone).filter(|p| !p.is_empty()) {
+            Some(periods) => periods,
+            None => match self.periods.first().filter(|p| !p.is_empty()) {
+                Some(periods) => periods,
+                None => return 3600.0 * distance_km / self.speed_kmh,
+            },
+        };
+
+        let mut remaining = distance_km;
+        let mut elapsed: f64 = 0.0;
+        loop {
+            // Periods are local times, so the offset is looked up again at each
+            // step and a DST change moves the next boundary
+            let at = depart + TimeDelta::milliseconds((elapsed * 1000.0).round() as i64);
+            let local = self.zone.local(at);
+            let second_of_day = local.num_seconds_from_midnight() as f64 + local.nanosecond() as f64 * 1e-9;
+            let (speed_kmh, until_next) = period_at(periods, second_of_day);
+            let boundary = local.naive_local() + TimeDelta::milliseconds((until_next * 1000.0).ceil() as i64);
+            let until_next = (self.zone.next_instant(boundary, at) - at).num_milliseconds() as f64 / 1000.0;
+
+            let reach = speed_kmh * until_next / 3600.0;
+            if reach >= remaining {
+                return elapsed + 3600.0 * remaining / speed_kmh;
+            }
+            remaining -= reach;
+            elapsed += until_next;
+        }
+    }
+
+    /// Arrival after driving `distance_km` from `depart`.
+    ///
+    /// At constant speed the travel time is truncated to the resolution.
+    /// With a profile the arrival itself is truncated to a multiple of it,
+    /// but never before `depart`, which keeps arrivals FIFO.
+    pub fn arrival(&self, distance_km: f64, speed_zone: usize, depart: DateTime<Utc>) -> DateTime<Utc> {
+        if !self.is_time_dependent() {
+            return depart + self.constant_time(distance_km);
+        }
+        let exact = depart + TimeDelta::milliseconds((self.exact_seconds(distance_km, speed_zone, depart) * 1000.0) as i64);
+        let stamp = exact.timestamp();
+        let truncated = DateTime::from_timestamp(stamp - stamp.rem_euclid(self.resolution_secs), 0).unwrap_or(exact);
+        truncated.max(depart)
+    }
+}
+
+/// Positive part of a duration in fractional minutes. Waiting and lateness
+/// are measured with it everywhere, so penalties agree across modules.
+pub fn minutes(delta: TimeDelta) -> f64 {
+    delta.num_milliseconds().max(0) as f64 / 60_000.0
+}
+
+fn sorted_periods(periods: &[SpeedPeriod]) -> Vec<(f64, f64)> {
+    let mut periods: Vec<(f64, f64)> = periods.iter()
+        .map(|p| (p.start.num_seconds_from_midnight() as f64, p.speed_kmh))
+        .collect();
+    periods.sort_by(|a, b| a.0.total_cmp(&b.0));
+    periods
+}
+
+/// Speed at second of day `at` and the seconds until it next changes.
+fn period_at(periods: &[(f64, f64)], at: f64) -> (f64, f64) {
+    // Before the first start we are still in the last period of the day before
+    let current = periods.iter().rposition(|(start, _)| *start <= at).unwrap_or(periods.len() - 1);
+    let next_start = periods[(current + 1) % periods.len()].0;
+    let mut until_next = (next_start - at).rem_euclid(SECONDS_PER_DAY);
+    if until_next == 0.0 {
+        until_next = SECONDS_PER_DAY;
+    }
+    (periods[current].1, until_next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    /// A model with one speed period per (start hour, km/h).
+    fn model(periods: &[(u32, f64)], resolution_secs: i64, zone: PlanningZone) -> TravelModel {
+        let periods = periods.iter()
+            .map(|&(hour, speed_kmh)| SpeedPeriod { start: NaiveTime::from_hms_opt(hour, 0, 0).unwrap(), speed_kmh })
+            .collect();
+        TravelModel::new(60.0, resolution_secs, zone, Some(SpeedProfile { periods, zones: Vec::new() }))
+    }
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(at).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn period_at_wraps_around_midnight() {
+        let periods = [(3600.0, 10.0), (7200.0, 20.0)];
+
+        assert_eq!(period_at(&periods, 0.0), (20.0, 3600.0));
+        assert_eq!(period_at(&periods, 3600.0), (10.0, 3600.0));
+        assert_eq!(period_at(&periods, 5000.0), (10.0, 2200.0));
+        assert_eq!(period_at(&periods, 7200.0), (20.0, 82_800.0));
+        assert_eq!(period_at(&[(0.0, 50.0)], 0.0), (50.0, SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn exact_seconds_drives_on_at_the_next_period_speed() {
+        let travel = model(&[(0, 60.0), (8, 30.0)], 60, PlanningZone::default());
+
+        // 30 km in the half hour before 08:00, then 10 km at 30 km/h
+        assert_eq!(travel.exact_seconds(40.0, 0, utc("2025-05-17T07:30:00+06:00")), 3000.0);
+        assert_eq!(travel.exact_seconds(20.0, 0, utc("2025-05-17T07:30:00+06:00")), 1200.0);
+    }
+
+    #[test]
+    fn exact_seconds_crosses_midnight_into_the_first_period() {
+        let travel = model(&[(6, 60.0), (22, 30.0)], 60, PlanningZone::default());
+
+        // 195 km at 30 km/h until 06:00, then 30 km at 60 km/h
+        assert_eq!(travel.exact_seconds(225.0, 0, utc("2025-05-17T23:30:00+06:00")), 25_200.0);
+    }
+
+    #[test]
+    fn leaving_later_never_arrives_earlier() {
+        let travel = model(&[(0, 80.0), (7, 20.0), (10, 60.0)], 1, PlanningZone::default());
+        let start = utc("2025-05-17T00:00:00+06:00");
+
+        let arrivals: Vec<f64> = (0..24 * 60 / 7)
+            .map(|k| 7.0 * 60.0 * k as f64 + travel.exact_seconds(35.0, 0, start + TimeDelta::minutes(7 * k)))
+            .collect();
+
+        assert!(arrivals.windows(2).all(|pair| pair[0] <= pair[1] + 1e-6), "{:?}", arrivals);
+    }
+
+    #[test]
+    fn arrivals_are_truncated_to_the_resolution_but_not_before_departure() {
+        let constant = TravelModel::new(60.0, 60, PlanningZone::default(), None);
+        assert_eq!(constant.truncate(119.9), TimeDelta::seconds(60));
+        assert_eq!(TravelModel::new(60.0, 1, PlanningZone::default(), None).truncate(119.9), TimeDelta::seconds(119));
+        assert_eq!(constant.arrival(1.99, 0, utc("2025-05-17T08:00:00+06:00")), utc("2025-05-17T08:01:00+06:00"));
+
+        // 52 minutes of driving, truncated to 5 minutes
+        let profiled = model(&[(0, 60.0), (8, 30.0)], 300, PlanningZone::default());
+        assert_eq!(profiled.arrival(41.0, 0, utc("2025-05-17T07:30:00+06:00")), utc("2025-05-17T08:20:00+06:00"));
+        let depart = utc("2025-05-17T07:30:30+06:00");
+        assert_eq!(profiled.arrival(0.1, 0, depart), depart);
+    }
+
+    #[test]
+    fn periods_follow_the_local_clock_across_dst_changes() {
+        let berlin = PlanningZone::from_name(Some("Europe/Berlin")).unwrap();
+
+        // Spring forward: from 01:00 CET, 04:00 CEST is two hours away
+        let spring = model(&[(0, 60.0), (4, 30.0)], 60, berlin);
+        assert_eq!(spring.exact_seconds(150.0, 0, utc("2024-03-31T00:00:00Z")), 10_800.0);
+
+        // Fall back: from 02:00 CEST, 03:00 CET is also two hours away
+        let fall = model(&[(0, 60.0), (3, 30.0)], 60, berlin);
+        assert_eq!(fall.exact_seconds(150.0, 0, utc("2024-10-27T00:00:00Z")), 10_800.0);
+    }
+
+    #[test]
+    fn minutes_keep_fractions_and_drop_negatives() {
+        assert_eq!(minutes(TimeDelta::seconds(90)), 1.5);
+        assert_eq!(minutes(TimeDelta::milliseconds(1_500)), 0.025);
+        assert_eq!(minutes(TimeDelta::minutes(-5)), 0.0);
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use crate::error::EvaluatorError;
+use crate::models::{Customer, PenaltyParams, Warehouse};
+use crate::pricing::haversine_distance;
+use crate::travel_time::{minutes, TravelModel};
+
+/// Road distance per straight-line kilometre assumed by the trip cost model.
+pub const ROAD_FACTOR: f64 = 1.65;
+/// Driving time relative to free-flow time at `speed_kmh`.
+pub const TRAFFIC_FACTOR: f64 = 1.1;
+
+/// Node of the location table the trip cost model works on. Customers
+/// carry their window and demand, warehouses only coordinates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub id: String,
+    pub lat: f64,
+    pub lng: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_start: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_end: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<f64>,
+}
+
+impl Location {
+    pub fn from_warehouse(id: &str, wh: &Warehouse) -> Self {
+        Self { id: id.to_string(), lat: wh.lat, lng: wh.lng, window_start: None, window_end: None, capacity: None }
+    }
+
+    pub fn from_customer(id: &str, cust: &Customer) -> Self {
+        Self {
+            id: id.to_string(),
+            lat: cust.lat,
+            lng: cust.lng,
+            window_start: Some(cust.window_start),
+            window_end: Some(cust.window_end),
+            capacity: Some(cust.capacity),
+        }
+    }
+}
+
+/// One leg of an evaluated trip. `arrival_time` is when the vehicle is done
+/// at `to`, i.e. the end of service for customers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripLeg {
+    pub from: String,
+    pub to: String,
+    pub distance_km: f64,
+    pub driving_minutes: f64,
+    pub waiting_minutes: f64,
+    pub service_minutes: f64,
+    pub departure_time: DateTime<Utc>,
+    pub arrival_time: DateTime<Utc>,
+    pub cost: f64,
+    pub is_legal: bool,
+    pub violations: Vec<String>,
+    pub waiting_penalty: f64,
+    pub time_window_penalty: f64,
+}
+
+/// Evaluated trip, in the same shape as the trip calculator's output.
+/// `total_cost` is the distance cost only; penalties are reported beside it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripCost {
+    pub legs: Vec<TripLeg>,
+    pub total_distance: f64,
+    pub total_driving: f64,
+    pub total_waiting: f64,
+    pub total_service: f64,
+    pub total_cost: f64,
+    pub is_legal: bool,
+    pub violations: Vec<String>,
+    pub departure_time: DateTime<Utc>,
+    pub completion_time: DateTime<Utc>,
+    pub total_waiting_penalty: f64,
+    pub total_time_window_penalty: f64,
+}
+
+/// In-process version of the trip calculator's cost model.
+///
+/// Distances are haversine times `ROAD_FACTOR`. A leg drives for the travel
+/// model's time at its departure (so speed profiles apply) times
+/// `TRAFFIC_FACTOR`, truncated to whole minutes. The vehicle leaves just in
+/// time to start the first customer at its window start, and later stops wait
+/// for their window.
+#[derive(Debug, Clone)]
+pub struct TripCostModel {
+    locations: HashMap<String, Location>,
+    cost_per_km: f64,
+    travel: TravelModel,
+    service_minutes: i64,
+    max_capacity: f64,
+    max_stops: usize,
+    penalties: PenaltyParams,
+}
+
+impl TripCostModel {
+    pub fn new(
+        locations: Vec<Location>,
+        cost_per_km: f64,
+        travel: TravelModel,
+        service_minutes: i64,
+        max_capacity: f64,
+        max_stops: usize,
+        penalties: PenaltyParams,
+    ) -> Self {
+        Self {
+            locations: locations.into_iter().map(|loc| (loc.id.clone(), loc)).collect(),
+            cost_per_km,
+            travel,
+            service_minutes,
+            max_capacity,
+            max_stops,
+            penalties,
+        }
+    }
+
+    /// Input document of the external trip calculator for one route.
+    pub fn calculator_input(&self, path: &[String], departure: DateTime<Utc>) -> serde_json::Value {
+        let mut input = self.calculator_instance();
+        if let (Some(input), serde_json::Value::Object(route)) = (input.as_object_mut(), calculator_route(path, departure)) {
+            input.extend(route);
+        }
+        input
+    }
+
+    /// Calculator input for one route with only the locations it visits, in
+    /// visiting order: all the route's cost depends on.
+    pub fn route_input(&self, path: &[String], departure: DateTime<Utc>) -> serde_json::Value {
+        let mut input = self.calculator_input(path, departure);
+        let visited: Vec<_> = path.iter().filter_map(|id| self.locations.get(id)).collect();
+        input["locations"] = serde_json::json!(visited);
+        input
+    }
+
+    /// The route-independent part of the calculator input: locations and
+    /// cost parameters. The planning timezone is included because speed
+    /// profile periods are local times of day.
+    pub fn calculator_instance(&self) -> serde_json::Value {
+        let mut instance = serde_json::json!({
+            "locations": self.locations.values().collect::<Vec<_>>(),
+            "cost_per_km": self.cost_per_km,
+            "speed_kmh": self.travel.speed_kmh(),
+            "timezone": self.travel.zone().name(),
+            "service_minutes": self.service_minutes,
+            "max_capacity": self.max_capacity,
+            "max_stops": self.max_stops,
+            "allow_violate_time_window": false,
+            "penalties": self.penalties,
+        });
+        if let Some(profile) = self.travel.profile() {
+            instance["speed_profile"] = serde_json::json!(profile);
+        }
+        instance
+    }
+
+    pub fn evaluate(&self, path: &[String], departure: DateTime<Utc>) -> Result<TripCost, EvaluatorError> {
+        let mut legs = Vec::with_capacity(path.len().saturating_sub(1));
+        let mut visited = HashSet::new();
+        let mut load = 0.0;
+        let mut stops = 0;
+        let mut clock = departure;
+
+        for (k, pair) in path.windows(2).enumerate() {
+            let from = self.location(&pair[0])?;
+            let to = self.location(&pair[1])?;
+
+            let distance_km = ROAD_FACTOR * haversine_distance((from.lat, from.lng), (to.lat, to.lng));
+            let speed_zone = self.travel.speed_zone(from.lat, from.lng);
+            let driving_minutes = self.travel.exact_seconds(distance_km, speed_zone, clock) / 60.0 * TRAFFIC_FACTOR;
+            let driving = TimeDelta::minutes(driving_minutes as i64);
+
+            let mut leg = TripLeg {
+                from: from.id.clone(),
+                to: to.id.clone(),
+                distance_km,
+                driving_minutes,
+                waiting_minutes: 0.0,
+                service_minutes: 0.0,
+                departure_time: clock,
+                arrival_time: clock + driving,
+                cost: self.cost_per_km * distance_km,
+                is_legal: true,
+                violations: Vec::new(),
+                waiting_penalty: 0.0,
+                time_window_penalty: 0.0,
+            };
+
+            if to.id.starts_with("C_") {
+                let (Some(window_start), Some(window_end)) = (to.window_start, to.window_end) else {
+                    return Err(EvaluatorError::MissingWindow(to.id.clone()));
+                };
+
+                if k == 0 {
+                    leg.departure_time = window_start - driving;
+                }
+                let arrival = leg.departure_time + driving;
+                let service_start = arrival.max(window_start);
+                let service_end = service_start + TimeDelta::minutes(self.service_minutes);
+
+                if !visited.insert(to.id.as_str()) {
+                    leg.violations.push(format!("Duplicate visit to {}", to.id));
+                }
+                if arrival > window_end {
+                    leg.violations.push(format!(
+                        "Arrival at {} after window ({} > {})",
+                        to.id, arrival.format("%H:%M"), window_end.format("%H:%M")
+                    ));
+                }
+                if service_end > window_end {
+                    leg.violations.push(format!(
+                        "Service at {} exceeds window ({} > {})",
+                        to.id, service_end.format("%H:%M"), window_end.format("%H:%M")
+                    ));
+                }
+
+                stops += 1;
+                load += to.capacity.unwrap_or(0.0);
+                if load > self.max_capacity {
+                    leg.violations.push("Capacity exceeded".to_string());
+                }
+
+                leg.waiting_minutes = minutes(service_start - arrival);
+                leg.service_minutes = self.service_minutes as f64;
+                leg.arrival_time = service_end;
+                leg.waiting_penalty = self.penalties.waiting_per_minute * leg.waiting_minutes;
+                leg.time_window_penalty = self.penalties.late_arrival_per_minute * minutes(arrival - window_end)
+                    + self.penalties.late_service_per_minute * minutes(service_end - window_end);
+            }
+
+            leg.is_legal = leg.violations.is_empty();
+            clock = leg.arrival_time;
+            legs.push(leg);
+        }
+
+        let mut violations: Vec<String> = legs.iter().flat_map(|leg| leg.violations.clone()).collect();
+        if stops > self.max_stops {
+            violations.push(format!("Exceeds max stops ({} > {})", stops, self.max_stops));
+        }
+
+        Ok(TripCost {
+            total_distance: legs.iter().map(|leg| leg.distance_km).sum(),
+            total_driving: legs.iter().map(|leg| leg.driving_minutes).sum(),
+            total_waiting: legs.iter().map(|leg| leg.waiting_minutes).sum(),
+            total_service: legs.iter().map(|leg| leg.service_minutes).sum(),
+            total_cost: legs.iter().map(|leg| leg.cost).sum(),
+            is_legal: violations.is_empty(),
+            violations,
+            departure_time: departure,
+            completion_time: clock,
+            total_waiting_penalty: legs.iter().map(|leg| leg.waiting_penalty).sum(),
+            total_time_window_penalty: legs.iter().map(|leg| leg.time_window_penalty).sum(),
+            legs,
+        })
+    }
+
+    fn location(&self, id: &str) -> Result<&Location, EvaluatorError> {
+        self.locations.get(id).ok_or_else(|| EvaluatorError::UnknownLocation(id.to_string()))
+    }
+}
+
+/// The per-route part of the calculator input.
+pub fn calculator_route(path: &[String], departure: DateTime<Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "path": path,
+        "departure": departure.to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{build_pricing, test_input};
+
+    /// `test_output.json` holds the calculator's cost of its route.
+    #[test]
+    fn matches_calculator_on_fixture() {
+        let expected: serde_json::Value = serde_json::from_str(include_str!("../test_output.json")).unwrap();
+        let path: Vec<String> = serde_json::from_value(expected["path"].clone()).unwrap();
+        let pricing = build_pricing(test_input()).unwrap();
+
+        let trip = pricing.evaluator().evaluate(&path, pricing.departure_from(&path[0])).unwrap();
+
+        assert!((trip.total_cost - expected["cost"].as_f64().unwrap()).abs() < 1e-9, "got {}", trip.total_cost);
+    }
+}
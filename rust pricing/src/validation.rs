@@ -0,0 +1,489 @@
+use chrono::NaiveDate;
+use std::collections::HashSet;
+use crate::sequencing::HELD_KARP_MAX_STOPS;
+use crate::timezone::PlanningZone;
+use crate::models::{DepartureSpec, EvaluatorConfig, InputData, IssueKind, MatrixFormat, SpeedPeriod, TravelMatrix, ValidationIssue, ValidationReport};
+
+impl InputData {
+    /// Checks the input for data the solver cannot work with and reports all
+    /// problems at once rather than stopping at the first.
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+        let mut issue = |kind: IssueKind, field: String, id: Option<String>, message: String| {
+            issues.push(ValidationIssue { kind, field, id, message });
+        };
+
+        let date = NaiveDate::parse_from_str(&self.planning_date, "%Y-%m-%d");
+        if date.is_err() {
+            issue(IssueKind::InvalidDate, "planning_date".into(), None, format!(
+                "Planning date '{}' is not a YYYY-MM-DD date", self.planning_date
+            ));
+        }
+        if self.departure_hour > 23 {
+            issue(IssueKind::OutOfRange, "departure_hour".into(), None, format!(
+                "Departure hour {} is not between 0 and 23", self.departure_hour
+            ));
+        }
+        match PlanningZone::from_name(self.timezone.as_deref()) {
+            Err(message) => issue(IssueKind::UnknownTimezone, "timezone".into(), None, message),
+            // Skipped by a DST change, e.g. 02:00 on the spring-forward day
+            Ok(zone) => if let Ok(date) = date {
+                if self.departure_hour <= 23 && zone.at_hour(date, self.departure_hour).is_none() {
+                    issue(IssueKind::InvalidDate, "departure_hour".into(), None, format!(
+                        "Departure hour {} does not exist on {} in the planning timezone", self.departure_hour, date
+                    ));
+                }
+            },
+        }
+
+        if self.speed_kmh <= 0.0 {
+            issue(IssueKind::NonPositive, "speed_kmh".into(), None, format!("Speed must be positive, got {}", self.speed_kmh));
+        }
+        if let Some(profile) = &self.speed_profile {
+            for (period, message) in period_problems(&profile.periods) {
+                issue(IssueKind::NonPositive, format!("speed_profile.periods[{}].speed_kmh", period), None, message);
+            }
+            let mut zone_ids = HashSet::new();
+            for (i, zone) in profile.zones.iter().enumerate() {
+                let field = format!("speed_profile.zones[{}]", i);
+                let id = Some(zone.id.clone());
+                if !zone_ids.insert(zone.id.as_str()) {
+                    issue(IssueKind::DuplicateId, format!("{}.id", field), id.clone(), format!("Speed zone id '{}' is used more than once", zone.id));
+                }
+                if zone.min_lat > zone.max_lat || zone.min_lng > zone.max_lng {
+                    issue(IssueKind::OutOfRange, field.clone(), id.clone(), format!("Speed zone '{}' has a minimum above its maximum", zone.id));
+                }
+                if zone.periods.is_empty() {
+                    issue(IssueKind::Missing, format!("{}.periods", field), id.clone(), format!("Speed zone '{}' needs at least one period", zone.id));
+                }
+                for (period, message) in period_problems(&zone.periods) {
+                    issue(IssueKind::NonPositive, format!("{}.periods[{}].speed_kmh", field, period), id.clone(), message);
+                }
+            }
+        }
+        if self.detour.factor <= 0.0 {
+            issue(IssueKind::NonPositive, "detour.factor".into(), None, format!(
+                "Detour factor must be positive, got {}", self.detour.factor
+            ));
+        }
+        let mut region_ids = HashSet::new();
+        for (i, region) in self.detour.regions.iter().enumerate() {
+            let field = format!("detour.regions[{}]", i);
+            let id = Some(region.id.clone());
+            if !region_ids.insert(region.id.as_str()) {
+                issue(IssueKind::DuplicateId, format!("{}.id", field), id.clone(), format!("Detour region id '{}' is used more than once", region.id));
+            }
+            if region.min_lat > region.max_lat || region.min_lng > region.max_lng {
+                issue(IssueKind::OutOfRange, field.clone(), id.clone(), format!("Detour region '{}' has a minimum above its maximum", region.id));
+            }
+            if region.factor <= 0.0 {
+                issue(IssueKind::NonPositive, format!("{}.factor", field), id.clone(), format!(
+                    "Detour factor must be positive, got {}", region.factor
+                ));
+            }
+        }
+        let mut classes: Vec<_> = self.detour.arc_classes.iter().collect();
+        classes.sort_by_key(|(class, _)| **class);
+        for (class, factor) in classes {
+            if *factor <= 0.0 {
+                let class = serde_json::to_value(class).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+                issue(IssueKind::NonPositive, format!("detour.arc_classes.{}", class), None, format!(
+                    "Detour factor must be positive, got {}", factor
+                ));
+            }
+        }
+        if self.exact_sequencing_max_stops > HELD_KARP_MAX_STOPS {
+            issue(IssueKind::OutOfRange, "exact_sequencing_max_stops".into(), None, format!(
+                "Exact sequencing is limited to {} stops, got {}", HELD_KARP_MAX_STOPS, self.exact_sequencing_max_stops
+            ));
+        }
+        if self.time_resolution_secs <= 0 {
+            issue(IssueKind::NonPositive, "time_resolution_secs".into(), None, format!(
+                "Time resolution must be positive, got {}", self.time_resolution_secs
+            ));
+        }
+        let non_negative = [
+            ("max_capacity", self.max_capacity),
+            ("cost_per_km", self.cost_per_km),
+            ("service_time", self.service_time as f64),
+            ("fixed_vehicle_cost", self.fixed_vehicle_cost),
+        ];
+        let optional = [
+            ("service_time_secs", self.service_time_secs.map(|secs| secs as f64)),
+            ("consistency_tolerance", self.consistency_tolerance),
+            ("time_limit_secs", self.time_limit_secs),
+            ("evaluator.timeout_secs", match &self.evaluator {
+                EvaluatorConfig::Subprocess { timeout_secs, .. } => *timeout_secs,
+                _ => None,
+            }),
+            ("road_graph.max_snap_km", self.road_graph.as_ref().map(|road_graph| road_graph.max_snap_km)),
+        ];
+        let non_negative = non_negative.into_iter()
+            .chain(optional.into_iter().filter_map(|(field, value)| value.map(|v| (field, v))));
+        for (field, value) in non_negative {
+            if !value.is_finite() {
+                issue(IssueKind::OutOfRange, field.into(), None, format!("{} must be a finite number, got {}", field, value));
+            } else if value < 0.0 {
+                issue(IssueKind::Negative, field.into(), None, format!("{} must not be negative, got {}", field, value));
+            }
+        }
+
+        if self.warehouses.is_empty() {
+            issue(IssueKind::Missing, "warehouses".into(), None, "At least one warehouse is needed".into());
+        }
+
+        let mut warehouse_ids = HashSet::new();
+        for (i, wh) in self.warehouses.iter().enumerate() {
+            let field = format!("warehouses[{}]", i);
+            let id = Some(format!("W_{}", wh.id));
+            if !warehouse_ids.insert(wh.id) {
+                issue(IssueKind::DuplicateId, format!("{}.id", field), id.clone(), format!("Warehouse id {} is used more than once", wh.id));
+            }
+            for (problem, message) in coordinate_problems(wh.lat, wh.lng) {
+                issue(IssueKind::OutOfRange, format!("{}.{}", field, problem), id.clone(), message);
+            }
+            if let Some(departure) = &wh.departure {
+                for (kind, problem, message) in departure_problems(departure) {
+                    issue(kind, format!("{}.departure.{}", field, problem), id.clone(), message);
+                }
+            }
+        }
+
+        let mut customer_ids = HashSet::new();
+        for (i, cust) in self.customers.iter().enumerate() {
+            let field = format!("customers[{}]", i);
+            let id = Some(format!("C_{}", cust.id));
+            if !customer_ids.insert(cust.id) {
+                issue(IssueKind::DuplicateId, format!("{}.id", field), id.clone(), format!("Customer id {} is used more than once", cust.id));
+            }
+            for (problem, message) in coordinate_problems(cust.lat, cust.lng) {
+                issue(IssueKind::OutOfRange, format!("{}.{}", field, problem), id.clone(), message);
+            }
+            if cust.capacity < 0.0 {
+                issue(IssueKind::Negative, format!("{}.capacity", field), id.clone(), format!(
+                    "Demand must not be negative, got {}", cust.capacity
+                ));
+            }
+            if cust.window_end < cust.window_start {
+                issue(IssueKind::InvertedWindow, format!("{}.window_end", field), id.clone(), format!(
+                    "Window ends at {} before it starts at {}", cust.window_end.to_rfc3339(), cust.window_start.to_rfc3339()
+                ));
+            }
+        }
+
+        let mut dual_keys: Vec<&String> = self.dual_values.keys().collect();
+        dual_keys.sort();
+        for key in dual_keys {
+            if !key.parse().is_ok_and(|id: i64| customer_ids.contains(&id)) {
+                issue(IssueKind::UnknownDual, format!("dual_values.{}", key), None, format!(
+                    "Dual value for '{}' matches no customer", key
+                ));
+            }
+        }
+
+        let mut vehicle_type_ids = HashSet::new();
+        for (i, vt) in self.vehicle_types.iter().enumerate() {
+            let field = format!("vehicle_types[{}]", i);
+            if !vehicle_type_ids.insert(vt.id.as_str()) {
+                issue(IssueKind::DuplicateId, format!("{}.id", field), Some(vt.id.clone()), format!(
+                    "Vehicle type id '{}' is used more than once", vt.id
+                ));
+            }
+            if let Some(departure) = &vt.departure {
+                for (kind, problem, message) in departure_problems(departure) {
+                    issue(kind, format!("{}.departure.{}", field, problem), Some(vt.id.clone()), message);
+                }
+            }
+            let mut keys: Vec<&String> = vt.warehouse_departures.keys().collect();
+            keys.sort();
+            for key in keys {
+                let departure_field = format!("{}.warehouse_departures.{}", field, key);
+                if !key.parse().is_ok_and(|id: i64| warehouse_ids.contains(&id)) {
+                    issue(IssueKind::UnknownWarehouse, departure_field.clone(), Some(vt.id.clone()), format!(
+                        "Departure for '{}' matches no warehouse", key
+                    ));
+                }
+                for (kind, problem, message) in departure_problems(&vt.warehouse_departures[key]) {
+                    issue(kind, format!("{}.{}", departure_field, problem), Some(vt.id.clone()), message);
+                }
+            }
+        }
+        if let Some(id) = &self.vehicle_type {
+            if !vehicle_type_ids.contains(id.as_str()) {
+                issue(IssueKind::UnknownVehicleType, "vehicle_type".into(), Some(id.clone()), format!(
+                    "Vehicle type '{}' is not in vehicle_types", id
+                ));
+            }
+        }
+
+        if let Some(matrix) = &self.matrix {
+            let table = matches!(matrix.format, MatrixFormat::OsrmTable | MatrixFormat::Valhalla);
+            if table && matrix.file.is_some() && matrix.sources.is_empty() {
+                issues.push(ValidationIssue {
+                    kind: IssueKind::Missing,
+                    field: "matrix.sources".into(),
+                    id: None,
+                    message: "A table file needs the node ids of its rows in 'sources'".into(),
+                });
+            }
+            // With a file, coverage can only be checked once it is read
+            issues.extend(self.matrix_issues(&matrix.matrix, matrix.requires_complete() && matrix.file.is_none()));
+        }
+
+        if let Some(road_graph) = &self.road_graph {
+            let mut classes: Vec<(&String, &f64)> = road_graph.speed_classes.iter().collect();
+            classes.sort_by(|a, b| a.0.cmp(b.0));
+            for (class, speed_kmh) in classes {
+                if *speed_kmh <= 0.0 {
+                    issues.push(ValidationIssue {
+                        kind: IssueKind::NonPositive,
+                        field: format!("road_graph.speed_classes.{}", class),
+                        id: None,
+                        message: format!("Speed of class '{}' must be positive, got {}", class, speed_kmh),
+                    });
+                }
+            }
+        }
+
+        ValidationReport { valid: issues.is_empty(), issues }
+    }
+
+    /// Entries of a travel matrix for this input that name no node or hold
+    /// a negative or non-finite value, and with `complete` the arcs of the
+    /// graph it has no distance or duration for.
+    pub fn matrix_issues(&self, matrix: &TravelMatrix, complete: bool) -> Vec<ValidationIssue> {
+        let nodes: HashSet<String> = self.warehouses.iter().map(|wh| format!("W_{}", wh.id))
+            .chain(self.customers.iter().map(|cust| format!("C_{}", cust.id)))
+            .collect();
+        let mut issues = Vec::new();
+        for (table, rows) in [("distances_km", &matrix.distances_km), ("durations_secs", &matrix.durations_secs)] {
+            let mut entries: Vec<(&String, &String, f64)> = rows.iter()
+                .flat_map(|(from, row)| row.iter().map(move |(to, value)| (from, to, *value)))
+                .collect();
+            entries.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+            for (from, to, value) in entries {
+                let field = format!("matrix.{}.{}.{}", table, from, to);
+                if let Some(unknown) = [from, to].into_iter().find(|node| !nodes.contains(*node)) {
+                    issues.push(ValidationIssue {
+                        kind: IssueKind::UnknownNode,
+                        field: field.clone(),
+                        id: Some(unknown.clone()),
+                        message: format!("Matrix entry for '{}' matches no warehouse or customer", unknown),
+                    });
+                }
+                if !(value >= 0.0 && value.is_finite()) {
+                    issues.push(ValidationIssue {
+                        kind: IssueKind::Negative,
+                        field,
+                        id: None,
+                        message: format!("Matrix values must be finite and not negative, got {}", value),
+                    });
+                }
+            }
+        }
+
+        if complete {
+            let mut warehouses: Vec<String> = self.warehouses.iter().map(|wh| format!("W_{}", wh.id)).collect();
+            let mut customers: Vec<String> = self.customers.iter().map(|cust| format!("C_{}", cust.id)).collect();
+            warehouses.sort_by_key(|node| (node.len(), node.clone()));
+            customers.sort_by_key(|node| (node.len(), node.clone()));
+            // The arcs `build_edges` adds: warehouse to customer and back, customer to customer
+            let arcs = warehouses.iter()
+                .flat_map(|wh| customers.iter().flat_map(move |cust| [(wh, cust), (cust, wh)]))
+                .chain(customers.iter().flat_map(|a| customers.iter().filter(move |b| *b != a).map(move |b| (a, b))));
+            for (from, to) in arcs {
+                let missing: Vec<&str> = [
+                    ("distances_km", matrix.distance_km(from, to)),
+                    ("durations_secs", matrix.duration_secs(from, to)),
+                ].into_iter().filter(|(_, value)| value.is_none()).map(|(table, _)| table).collect();
+                if !missing.is_empty() {
+                    issues.push(ValidationIssue {
+                        kind: IssueKind::Missing,
+                        field: format!("matrix.{}.{}", from, to),
+                        id: None,
+                        message: format!("No {} from {} to {}", missing.join(" or "), from, to),
+                    });
+                }
+            }
+        }
+        issues
+    }
+}
+
+/// (kind, field, message) for each way a departure contradicts itself.
+fn departure_problems(departure: &DepartureSpec) -> Vec<(IssueKind, &'static str, String)> {
+    let mut problems = Vec::new();
+    if departure.at.is_some() && (departure.earliest.is_some() || departure.latest.is_some()) {
+        problems.push((IssueKind::ConflictingDeparture, "at", "Give either 'at' or 'earliest'/'latest', not both".to_string()));
+    }
+    if let (Some(earliest), Some(latest)) = (departure.earliest, departure.latest) {
+        if latest < earliest {
+            problems.push((IssueKind::InvertedWindow, "latest", format!(
+                "Departure window ends at {} before it starts at {}", latest.to_rfc3339(), earliest.to_rfc3339()
+            )));
+        }
+    }
+    problems
+}
+
+/// (index, message) for each speed period that could never be left.
+fn period_problems(periods: &[SpeedPeriod]) -> Vec<(usize, String)> {
+    periods.iter().enumerate()
+        .filter(|(_, period)| period.speed_kmh <= 0.0)
+        .map(|(i, period)| (i, format!("Speed must be positive, got {}", period.speed_kmh)))
+        .collect()
+}
+
+/// (field, message) for each coordinate outside its range.
+fn coordinate_problems(lat: f64, lng: f64) -> Vec<(&'static str, String)> {
+    let mut problems = Vec::new();
+    if !(-90.0..=90.0).contains(&lat) {
+        problems.push(("lat", format!("Latitude {} is not between -90 and 90", lat)));
+    }
+    if !(-180.0..=180.0).contains(&lng) {
+        problems.push(("lng", format!("Longitude {} is not between -180 and 180", lng)));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{DepartureSpec, EvaluatorConfig, InputData, IssueKind, VehicleType};
+    use crate::test_input;
+
+    /// (kind, field) of every issue, in report order.
+    fn issues(input: &InputData) -> Vec<(IssueKind, String)> {
+        input.validate().issues.into_iter().map(|issue| (issue.kind, issue.field)).collect()
+    }
+
+    fn has(input: &InputData, kind: IssueKind, field: &str) -> bool {
+        issues(input).contains(&(kind, field.to_string()))
+    }
+
+    #[test]
+    fn fixture_is_valid() {
+        assert_eq!(issues(&test_input()), Vec::new());
+    }
+
+    #[test]
+    fn duplicate_ids_are_reported() {
+        let mut input = test_input();
+        input.customers[1].id = input.customers[0].id;
+        input.warehouses[2].id = input.warehouses[0].id;
+
+        assert!(has(&input, IssueKind::DuplicateId, "customers[1].id"));
+        assert!(has(&input, IssueKind::DuplicateId, "warehouses[2].id"));
+        assert!(!has(&input, IssueKind::DuplicateId, "customers[0].id"), "the first use is fine");
+    }
+
+    #[test]
+    fn window_ending_before_it_starts_is_inverted() {
+        let mut input = test_input();
+        input.customers[3].window_end = input.customers[3].window_start - chrono::TimeDelta::minutes(1);
+
+        assert_eq!(issues(&input), vec![(IssueKind::InvertedWindow, "customers[3].window_end".to_string())]);
+
+        // An empty window is still a window.
+        input.customers[3].window_end = input.customers[3].window_start;
+        assert!(input.validate().valid);
+    }
+
+    #[test]
+    fn negative_and_non_positive_values() {
+        let mut input = test_input();
+        input.customers[0].capacity = -1.0;
+        input.max_capacity = -5.0;
+        input.speed_kmh = -50.0;
+        assert!(has(&input, IssueKind::Negative, "customers[0].capacity"));
+        assert!(has(&input, IssueKind::Negative, "max_capacity"));
+        assert!(has(&input, IssueKind::NonPositive, "speed_kmh"));
+
+        let mut input = test_input();
+        input.speed_kmh = 0.0;
+        input.max_capacity = 0.0;
+        assert_eq!(issues(&input), vec![(IssueKind::NonPositive, "speed_kmh".to_string())]);
+    }
+
+    #[test]
+    fn coordinates_out_of_range() {
+        let mut input = test_input();
+        input.customers[2].lat = 91.0;
+        input.warehouses[0].lng = -180.5;
+        input.warehouses[1].lat = -90.0;
+
+        assert_eq!(issues(&input), vec![
+            (IssueKind::OutOfRange, "warehouses[0].lng".to_string()),
+            (IssueKind::OutOfRange, "customers[2].lat".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn dual_keys_must_name_a_customer() {
+        let mut input = test_input();
+        input.dual_values.insert("404".to_string(), 10.0);
+        input.dual_values.insert("C_1".to_string(), 10.0);
+
+        assert_eq!(issues(&input), vec![
+            (IssueKind::UnknownDual, "dual_values.404".to_string()),
+            (IssueKind::UnknownDual, "dual_values.C_1".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn calculator_timeout_must_be_finite_and_non_negative() {
+        let mut input = test_input();
+        let calculator = |timeout_secs| EvaluatorConfig::Subprocess {
+            path: "calculator".to_string(),
+            args: Vec::new(),
+            timeout_secs,
+            protocol: Default::default(),
+        };
+
+        input.evaluator = calculator(Some(-1.0));
+        assert_eq!(issues(&input), vec![(IssueKind::Negative, "evaluator.timeout_secs".to_string())]);
+        input.evaluator = calculator(Some(f64::NAN));
+        assert_eq!(issues(&input), vec![(IssueKind::OutOfRange, "evaluator.timeout_secs".to_string())]);
+        input.evaluator = calculator(None);
+        assert!(input.validate().valid);
+    }
+
+    #[test]
+    fn all_problems_are_reported_at_once() {
+        let mut input = test_input();
+        input.speed_kmh = 0.0;
+        input.customers[0].lat = 100.0;
+        input.dual_values.insert("x".to_string(), 1.0);
+
+        let report = input.validate();
+        assert!(!report.valid);
+        assert_eq!(report.issues.len(), 3);
+    }
+
+    #[test]
+    fn contradicting_departures_are_reported() {
+        let at = |text| Some(chrono::DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&chrono::Utc));
+        let mut input = test_input();
+        input.warehouses[0].departure = Some(DepartureSpec {
+            at: at("2025-05-17T08:00:00+06:00"),
+            earliest: at("2025-05-17T08:00:00+06:00"),
+            latest: None,
+        });
+        input.vehicle_types = vec![VehicleType {
+            id: "van".to_string(),
+            departure: Some(DepartureSpec {
+                at: None,
+                earliest: at("2025-05-17T09:00:00+06:00"),
+                latest: at("2025-05-17T08:00:00+06:00"),
+            }),
+            warehouse_departures: [("9".to_string(), DepartureSpec::default())].into(),
+        }];
+        input.vehicle_type = Some("truck".to_string());
+
+        assert_eq!(issues(&input), vec![
+            (IssueKind::ConflictingDeparture, "warehouses[0].departure.at".to_string()),
+            (IssueKind::InvertedWindow, "vehicle_types[0].departure.latest".to_string()),
+            (IssueKind::UnknownWarehouse, "vehicle_types[0].warehouse_departures.9".to_string()),
+            (IssueKind::UnknownVehicleType, "vehicle_type".to_string()),
+        ]);
+    }
+}